@@ -1,4 +1,7 @@
-use crate::{ray::Ray, vec3::Point3};
+use crate::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 #[derive(Clone, Copy)]
 pub struct AABB {
     minimum: Point3,
@@ -28,16 +31,40 @@ impl AABB {
         self.maximum
     }
 
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        let direction = r.direction();
+        let inv_dir = Vec3::new(
+            1.0 / direction.x(),
+            1.0 / direction.y(),
+            1.0 / direction.z(),
+        );
+        self.hit_with_inverse(&r.origin(), &inv_dir, t_min, t_max)
+    }
+
+    pub fn hit_with_inverse(
+        &self,
+        origin: &Point3,
+        inv_dir: &Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> bool {
         let mut t_min = t_min;
         let mut t_max = t_max;
 
         for a in 0..3 {
-            let inv_d = 1.0 / r.direction()[a];
-            let mut t0 = (self.min()[a] - r.origin()[a]) * inv_d;
-            let mut t1 = (self.max()[a] - r.origin()[a]) * inv_d;
+            let mut t0 = (self.minimum[a] - origin[a]) * inv_dir[a];
+            let mut t1 = (self.maximum[a] - origin[a]) * inv_dir[a];
 
-            if inv_d < 0.0 {
+            if inv_dir[a] < 0.0 {
                 std::mem::swap(&mut t0, &mut t1);
             }
 
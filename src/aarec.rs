@@ -77,6 +77,8 @@ impl Hittable for XYRect {
         rec.t = t;
         let outward_normal = Vec3::new(0.0, 0.0, 1.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.bitangent = Vec3::new(0.0, self.y1 - self.y0, 0.0);
         rec.mat = self.mat.clone();
         rec.p = r.at(t);
 
@@ -154,6 +156,8 @@ impl Hittable for XZRect {
         rec.t = t;
         let outward_normal = Vec3::new(0.0, 1.0, 0.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.bitangent = Vec3::new(0.0, 0.0, self.z1 - self.z0);
         rec.mat = self.mat.clone();
         rec.p = r.at(t);
 
@@ -231,6 +235,8 @@ impl Hittable for YZRect {
         rec.t = t;
         let outward_normal = Vec3::new(1.0, 0.0, 0.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Vec3::new(0.0, self.y1 - self.y0, 0.0);
+        rec.bitangent = Vec3::new(0.0, 0.0, self.z1 - self.z0);
         rec.mat = self.mat.clone();
         rec.p = r.at(t);
 
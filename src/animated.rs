@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::{surrounding_box, AABB},
+    hittable::{HitRecord, Hittable},
+    mat4::{Quat, Transform},
+    ray::Ray,
+    vec3::Vec3,
+};
+
+// Largest rotation between two poses sampled for the motion bounds.
+const MAX_STEP_ANGLE: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, translation: Vec3, rotation: Quat, scale: Vec3) -> Keyframe {
+        Keyframe {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+}
+
+pub struct Animated {
+    ptr: Arc<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animated {
+    pub fn new(p: Arc<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Animated {
+        assert!(
+            !keyframes.is_empty(),
+            "animation needs at least one keyframe"
+        );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Animated { ptr: p, keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // Poses before the first and after the last keyframe are held.
+    fn pose(&self, time: f32) -> (Vec3, Quat, Vec3) {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 || next == self.keyframes.len() {
+            let k = &self.keyframes[next.saturating_sub(1)];
+            return (k.translation, k.rotation, k.scale);
+        }
+
+        let a = &self.keyframes[next - 1];
+        let b = &self.keyframes[next];
+        let t = (time - a.time) / (b.time - a.time);
+        (
+            a.translation + t * (b.translation - a.translation),
+            Quat::slerp(&a.rotation, &b.rotation, t),
+            a.scale + t * (b.scale - a.scale),
+        )
+    }
+
+    pub fn transform_at(&self, time: f32) -> Transform {
+        let (translation, rotation, scale) = self.pose(time);
+        Transform::from_parts(&translation, &rotation, &scale)
+    }
+
+    // Translation and scale move the corners of the object box along straight
+    // lines, so sampling at keyframes is exact for them. Rotation sweeps the
+    // corners along arcs: segments are subdivided until each step turns by at
+    // most MAX_STEP_ANGLE, and the result is padded by the arcs' sagitta.
+    fn motion_box(&self, object_box: &AABB, time0: f32, time1: f32) -> AABB {
+        let first = self.keyframes[0].time;
+        let last = self.keyframes[self.keyframes.len() - 1].time;
+        let start = time0.clamp(first, last);
+        let end = time1.clamp(first, last);
+
+        let mut times = vec![start];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&t| t > start && t < end),
+        );
+        times.push(end);
+
+        let mut extent = Vec3::default();
+        for c in 0..3 {
+            extent[c] = f32::max(object_box.min()[c].abs(), object_box.max()[c].abs());
+        }
+        let max_scale = self
+            .keyframes
+            .iter()
+            .flat_map(|k| k.scale.e)
+            .fold(0.0, |acc: f32, s| acc.max(s.abs()));
+        let radius = extent.length() * max_scale;
+
+        let mut output_box = self.transform_at(start).bounding_box(object_box);
+        let mut padding: f32 = 0.0;
+        for pair in times.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let angle = Quat::angle_between(&self.pose(a).1, &self.pose(b).1);
+            let steps = ((angle / MAX_STEP_ANGLE).ceil() as usize).max(1);
+            padding = padding.max(radius * (1.0 - (0.5 * angle / steps as f32).cos()));
+
+            for i in 1..=steps {
+                let time = a + (b - a) * i as f32 / steps as f32;
+                output_box =
+                    surrounding_box(output_box, self.transform_at(time).bounding_box(object_box));
+            }
+        }
+
+        let padding = Vec3::new(padding, padding, padding);
+        AABB::new(&(output_box.min() - padding), &(output_box.max() + padding))
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let transform = self.transform_at(r.time());
+        let object_ray = transform.ray_to_object(r);
+        if !self.ptr.hit(&object_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        transform.record_to_world(r, rec);
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
+        let mut object_box = AABB::default();
+        if !self.ptr.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+
+        *output_box = self.motion_box(&object_box, time0, time1);
+        true
+    }
+}
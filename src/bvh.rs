@@ -1,118 +1,873 @@
-use std::{cmp::Ordering, sync::Arc};
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt::Display,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
     aabb::{surrounding_box, AABB},
-    hittable::Hittable,
+    hittable::{HitRecord, Hittable},
     hittable_list::HittableList,
-    utils::random_i32_with_range,
+    ray::Ray,
+    thread_pool::ThreadPool,
+    vec3::{Point3, Vec3},
 };
 
-pub struct BvhNode {
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+pub const MAX_LEAF_SIZE: usize = 4;
+const SAH_BINS: usize = 16;
+const TRAVERSAL_COST: f32 = 0.125;
+const INTERSECTION_COST: f32 = 1.0;
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+const PARALLEL_SUBTREE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BvhStats {
+    pub primitives: usize,
+    pub interior_nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    pub sah_cost: f32,
+    pub build_time: Duration,
+}
+
+impl Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} primitive(s), {} interior node(s), {} leaves (max {} per leaf), depth {}, SAH cost {:.2}, built in {:.1} ms",
+            self.primitives,
+            self.interior_nodes,
+            self.leaves,
+            self.max_leaf_size,
+            self.max_depth,
+            self.sah_cost,
+            self.build_time.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum RefitError {
+    IndexOutOfRange { index: usize, len: usize },
+    Unbounded { index: usize },
+}
+
+impl Display for RefitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefitError::IndexOutOfRange { index, len } => write!(
+                f,
+                "object index {} out of range (have {} objects)",
+                index, len
+            ),
+            RefitError::Unbounded { index } => write!(
+                f,
+                "object {} has no bounding box and cannot be placed in a BVH",
+                index
+            ),
+        }
+    }
+}
+
+impl Error for RefitError {}
+
+// Builds the BVHs below the top level, such as meshes, shared geometry and
+// object groups, on the thread pool when there is one, and keeps the
+// statistics of each build for the build report.
+pub struct BvhBuilder<'a> {
+    pool: Option<&'a ThreadPool>,
+    stats: RefCell<Vec<BvhStats>>,
+}
+
+impl<'a> BvhBuilder<'a> {
+    pub fn new(pool: Option<&'a ThreadPool>) -> BvhBuilder<'a> {
+        BvhBuilder {
+            pool,
+            stats: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn build(&self, objects: &[Arc<dyn Hittable>], time0: f32, time1: f32) -> BvhNode {
+        let (bvh, stats) = BvhNode::build(objects, time0, time1, self.pool);
+        self.stats.borrow_mut().push(stats);
+        bvh
+    }
+
+    pub fn stats(&self) -> Vec<BvhStats> {
+        self.stats.borrow().clone()
+    }
+}
+
+struct BuildPrimitive {
+    index: usize,
+    object: Arc<dyn Hittable>,
+    aabb_box: AABB,
+    centroid: Point3,
+}
+
+enum BuildNode {
+    Leaf {
+        aabb_box: AABB,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        aabb_box: AABB,
+        axis: usize,
+        children: Box<(BuildNode, BuildNode)>,
+    },
+    // A subtree handed to the thread pool, identified by its job index.
+    Pending(usize),
+}
+
+struct FlattenContext {
+    nodes: Vec<LinearNode>,
+    subtrees: Vec<Option<BuildNode>>,
+    root_area: f32,
+    stats: BvhStats,
+}
+
+struct SubtreeResult {
+    job: usize,
+    offset: usize,
+    node: BuildNode,
+    primitives: Vec<BuildPrimitive>,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    count: usize,
+    aabb_box: Option<AABB>,
+}
+
+// Interior nodes store the index of their second child in `offset`, the
+// first child always follows its parent. Leaves store a primitive range.
+#[derive(Clone, Copy)]
+struct LinearNode {
     aabb_box: AABB,
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+// Where an object of the list the BVH was built from ended up.
+#[derive(Clone, Copy)]
+enum Slot {
+    Tree(u32),
+    Unbounded(u32),
+}
+
+// Objects without a bounding box, such as infinite planes, stay out of the
+// tree and are tested against every ray next to it. A BVH holding any of
+// them has no bounding box itself.
+#[derive(Clone)]
+pub struct BvhNode {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Hittable>>,
+    unbounded: Vec<Arc<dyn Hittable>>,
+    slots: Vec<Slot>,
+    leaf_of: Vec<u32>,
+    stats: BvhStats,
+    build_cost: f32,
 }
 
+const TRAVERSAL_STACK_SIZE: usize = 64;
+
 impl BvhNode {
     pub fn with_hittable_vec(
-        src_objects: &Vec<Arc<dyn Hittable>>,
+        src_objects: &[Arc<dyn Hittable>],
         start: usize,
         end: usize,
         time0: f32,
         time1: f32,
     ) -> BvhNode {
-        let mut objects = src_objects.clone();
+        BvhNode::with_stats(&src_objects[start..end], time0, time1).0
+    }
 
-        let axis = random_i32_with_range(0, 2) as usize;
+    pub fn with_hittable_list(list: &HittableList, time0: f32, time1: f32) -> BvhNode {
+        BvhNode::with_stats(&list.objects, time0, time1).0
+    }
 
-        let box_compare = |a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize| {
-            let mut box_a = AABB::default();
-            let mut box_b = AABB::default();
+    pub fn with_stats(
+        objects: &[Arc<dyn Hittable>],
+        time0: f32,
+        time1: f32,
+    ) -> (BvhNode, BvhStats) {
+        BvhNode::build(objects, time0, time1, None)
+    }
 
-            if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-                eprintln!("No bounding box in bvh construct.\n");
-            }
+    pub fn with_thread_pool(
+        objects: &[Arc<dyn Hittable>],
+        time0: f32,
+        time1: f32,
+        pool: &ThreadPool,
+    ) -> (BvhNode, BvhStats) {
+        BvhNode::build(objects, time0, time1, Some(pool))
+    }
 
-            box_a.min()[axis].total_cmp(&box_b.min()[axis])
-        };
-        let box_x_compare = |a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>| box_compare(a, b, 0);
-        let box_y_compare = |a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>| box_compare(a, b, 1);
-        let box_z_compare = |a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>| box_compare(a, b, 2);
-
-        let comparator: Box<dyn Fn(&Arc<dyn Hittable>, &Arc<dyn Hittable>) -> Ordering> = match axis
-        {
-            0 => Box::new(box_x_compare),
-            1 => Box::new(box_y_compare),
-            2 => Box::new(box_z_compare),
-            _ => Box::new(box_x_compare),
-        };
+    fn build(
+        objects: &[Arc<dyn Hittable>],
+        time0: f32,
+        time1: f32,
+        pool: Option<&ThreadPool>,
+    ) -> (BvhNode, BvhStats) {
+        let start_time = Instant::now();
+        let pool = pool.filter(|pool| pool.size() > 1 && objects.len() >= PARALLEL_BUILD_THRESHOLD);
 
-        let object_span = end - start;
+        let mut primitives = match pool {
+            Some(pool) => build_primitives_parallel(objects, time0, time1, pool),
+            None => build_primitives(objects, 0, time0, time1),
+        };
 
-        let (left, right) = match object_span {
-            1 => (Arc::clone(&objects[start]), Arc::clone(&objects[start])),
-            2 => match comparator(&objects[start], &objects[start + 1]) {
-                Ordering::Less => (Arc::clone(&objects[start]), Arc::clone(&objects[start + 1])),
-                _ => (Arc::clone(&objects[start + 1]), Arc::clone(&objects[start])),
+        let mut context = FlattenContext {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            subtrees: vec![],
+            root_area: 0.0,
+            stats: BvhStats {
+                primitives: primitives.len(),
+                ..BvhStats::default()
             },
-            _ => {
-                let slice = &mut objects[start..end];
-                slice.sort_by(comparator);
-
-                let mid = start + object_span / 2;
-
-                let left: Arc<dyn Hittable> = Arc::new(BvhNode::with_hittable_vec(
-                    &objects, start, mid, time0, time1,
-                ));
-                let right: Arc<dyn Hittable> =
-                    Arc::new(BvhNode::with_hittable_vec(&objects, mid, end, time0, time1));
-                (left, right)
+        };
+        if !primitives.is_empty() {
+            context.root_area = bounds_of(&primitives).surface_area();
+            let root = match pool {
+                Some(pool) => {
+                    let (root, subtrees, ordered) = build_parallel(primitives, pool);
+                    context.subtrees = subtrees;
+                    primitives = ordered;
+                    root
+                }
+                None => build_subtree(&mut primitives, 0),
+            };
+            flatten(root, 0, &mut context);
+        }
+        context.stats.build_time = start_time.elapsed();
+
+        let mut slots = vec![Slot::Unbounded(0); objects.len()];
+        let mut bounded = vec![false; objects.len()];
+        for (slot, primitive) in primitives.iter().enumerate() {
+            slots[primitive.index] = Slot::Tree(slot as u32);
+            bounded[primitive.index] = true;
+        }
+        let mut unbounded = vec![];
+        for (index, object) in objects.iter().enumerate() {
+            if !bounded[index] {
+                slots[index] = Slot::Unbounded(unbounded.len() as u32);
+                unbounded.push(Arc::clone(object));
+            }
+        }
+        let mut leaf_of = vec![0; primitives.len()];
+        for (index, node) in context.nodes.iter().enumerate() {
+            if node.count == 0 {
+                continue;
+            }
+            let start = node.offset as usize;
+            for leaf in &mut leaf_of[start..start + node.count as usize] {
+                *leaf = index as u32;
             }
+        }
+
+        let node = BvhNode {
+            nodes: context.nodes,
+            primitives: primitives.into_iter().map(|p| p.object).collect(),
+            unbounded,
+            slots,
+            leaf_of,
+            stats: context.stats,
+            build_cost: context.stats.sah_cost,
         };
+        (node, context.stats)
+    }
 
-        let mut box_left = AABB::default();
-        let mut box_right = AABB::default();
+    pub fn stats(&self) -> BvhStats {
+        self.stats
+    }
 
-        if !left.bounding_box(time0, time1, &mut box_left)
-            || !right.bounding_box(time0, time1, &mut box_right)
-        {
-            eprintln!("No bounding box in bvh construct.\n");
+    // Replaces the given primitives (indexed as in the list the BVH was built
+    // from) and recomputes the bounds of the affected nodes bottom-up. The
+    // topology is left unchanged. Objects without a bounding box can only
+    // replace others without one. Nothing is replaced if any of the changes
+    // is invalid.
+    pub fn refit(
+        &mut self,
+        changes: &[(usize, Arc<dyn Hittable>)],
+        time0: f32,
+        time1: f32,
+    ) -> Result<BvhStats, RefitError> {
+        let mut aabb_box = AABB::default();
+        for (index, object) in changes {
+            match self.slots.get(*index) {
+                Some(Slot::Tree(_)) => {
+                    if !object.bounding_box(time0, time1, &mut aabb_box) {
+                        return Err(RefitError::Unbounded { index: *index });
+                    }
+                }
+                Some(Slot::Unbounded(_)) => {}
+                None => {
+                    return Err(RefitError::IndexOutOfRange {
+                        index: *index,
+                        len: self.slots.len(),
+                    })
+                }
+            }
         }
 
-        let aabb_box = surrounding_box(box_left, box_right);
+        let start_time = Instant::now();
+        let mut dirty = vec![false; self.nodes.len()];
+        for (index, object) in changes {
+            match self.slots[*index] {
+                Slot::Tree(slot) => {
+                    let slot = slot as usize;
+                    self.primitives[slot] = Arc::clone(object);
+                    dirty[self.leaf_of[slot] as usize] = true;
+                }
+                Slot::Unbounded(slot) => self.unbounded[slot as usize] = Arc::clone(object),
+            }
+        }
 
-        BvhNode {
-            left,
-            right,
-            aabb_box,
+        // Children are always stored after their parent.
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            if node.count > 0 {
+                if !dirty[index] {
+                    continue;
+                }
+                let start = node.offset as usize;
+                let objects = &self.primitives[start..start + node.count as usize];
+                self.nodes[index].aabb_box = objects
+                    .iter()
+                    .map(|object| primitive_box(object, time0, time1))
+                    .reduce(surrounding_box)
+                    .unwrap();
+            } else {
+                let (left, right) = (index + 1, node.offset as usize);
+                if !dirty[left] && !dirty[right] {
+                    continue;
+                }
+                dirty[index] = true;
+                self.nodes[index].aabb_box =
+                    surrounding_box(self.nodes[left].aabb_box, self.nodes[right].aabb_box);
+            }
         }
+
+        self.stats.sah_cost = self.sah_cost();
+        self.stats.build_time = start_time.elapsed();
+        Ok(self.stats)
     }
 
-    pub fn with_hittable_list(list: &HittableList, time0: f32, time1: f32) -> BvhNode {
-        BvhNode::with_hittable_vec(&list.objects, 0, list.objects.len(), time0, time1)
+    // Refits, then rebuilds from scratch, on `pool` when given, if the SAH
+    // cost has grown beyond `max_cost_ratio` times the cost of the last full
+    // build.
+    pub fn refit_or_rebuild(
+        &mut self,
+        changes: &[(usize, Arc<dyn Hittable>)],
+        time0: f32,
+        time1: f32,
+        max_cost_ratio: f32,
+        pool: Option<&ThreadPool>,
+    ) -> Result<(BvhStats, bool), RefitError> {
+        let stats = self.refit(changes, time0, time1)?;
+        if stats.sah_cost <= self.build_cost * max_cost_ratio {
+            return Ok((stats, false));
+        }
+
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::with_capacity(self.slots.len());
+        for slot in &self.slots {
+            objects.push(match *slot {
+                Slot::Tree(slot) => Arc::clone(&self.primitives[slot as usize]),
+                Slot::Unbounded(slot) => Arc::clone(&self.unbounded[slot as usize]),
+            });
+        }
+        let (bvh, stats) = BvhNode::build(&objects, time0, time1, pool);
+        *self = bvh;
+        Ok((stats, true))
     }
-}
 
-impl Hittable for BvhNode {
-    fn hit(
+    fn sah_cost(&self) -> f32 {
+        let root_area = match self.nodes.first() {
+            Some(node) => node.aabb_box.surface_area(),
+            None => return 0.0,
+        };
+        self.nodes
+            .iter()
+            .map(|node| {
+                let area = relative_area(&node.aabb_box, root_area);
+                if node.count > 0 {
+                    INTERSECTION_COST * node.count as f32 * area
+                } else {
+                    TRAVERSAL_COST * area
+                }
+            })
+            .sum()
+    }
+
+    fn traverse(
         &self,
-        r: &crate::ray::Ray,
+        r: &Ray,
         t_min: f32,
         t_max: f32,
-        rec: &mut crate::hittable::HitRecord,
+        rec: &mut HitRecord,
+        stack: &mut [usize],
     ) -> bool {
-        if !self.aabb_box.hit(r, t_min, t_max) {
-            return false;
+        let origin = r.origin();
+        let direction = r.direction();
+        let inv_dir = Vec3::new(
+            1.0 / direction.x(),
+            1.0 / direction.y(),
+            1.0 / direction.z(),
+        );
+        let dir_is_neg = [inv_dir.x() < 0.0, inv_dir.y() < 0.0, inv_dir.z() < 0.0];
+
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node
+                .aabb_box
+                .hit_with_inverse(&origin, &inv_dir, t_min, closest_so_far)
+            {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for object in &self.primitives[start..start + node.count as usize] {
+                        if object.hit(r, t_min, closest_so_far, rec) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                } else {
+                    // Visit the child nearer to the ray origin first.
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+
+        hit_anything
+    }
+}
+
+// Only for objects known to have a bounding box.
+fn primitive_box(object: &Arc<dyn Hittable>, time0: f32, time1: f32) -> AABB {
+    let mut aabb_box = AABB::default();
+    object.bounding_box(time0, time1, &mut aabb_box);
+    aabb_box
+}
+
+// Returns `None` for objects without a bounding box.
+fn build_primitive(
+    index: usize,
+    object: &Arc<dyn Hittable>,
+    time0: f32,
+    time1: f32,
+) -> Option<BuildPrimitive> {
+    let mut aabb_box = AABB::default();
+    if !object.bounding_box(time0, time1, &mut aabb_box) {
+        return None;
+    }
+    Some(BuildPrimitive {
+        index,
+        object: Arc::clone(object),
+        aabb_box,
+        centroid: aabb_box.centroid(),
+    })
+}
+
+fn build_primitives(
+    objects: &[Arc<dyn Hittable>],
+    first_index: usize,
+    time0: f32,
+    time1: f32,
+) -> Vec<BuildPrimitive> {
+    objects
+        .iter()
+        .enumerate()
+        .filter_map(|(i, object)| build_primitive(first_index + i, object, time0, time1))
+        .collect()
+}
+
+fn build_primitives_parallel(
+    objects: &[Arc<dyn Hittable>],
+    time0: f32,
+    time1: f32,
+    pool: &ThreadPool,
+) -> Vec<BuildPrimitive> {
+    let chunk_size = objects.len().div_ceil(pool.size());
+    let (sender, receiver) = channel();
+
+    let mut jobs = 0;
+    for (index, chunk) in objects.chunks(chunk_size).enumerate() {
+        let chunk = chunk.to_vec();
+        let sender = sender.clone();
+        pool.execute(move || {
+            let primitives = build_primitives(&chunk, index * chunk_size, time0, time1);
+            let _ = sender.send((index, primitives));
+        });
+        jobs += 1;
+    }
+    // With only the jobs holding senders, a job that panics ends the loop
+    // below instead of leaving it waiting forever.
+    drop(sender);
+
+    let mut chunks: Vec<Option<Vec<BuildPrimitive>>> = (0..jobs).map(|_| None).collect();
+    for _ in 0..jobs {
+        let (index, chunk) = receiver.recv().expect("a BVH build job panicked");
+        chunks[index] = Some(chunk);
+    }
+    chunks.into_iter().flatten().flatten().collect()
+}
+
+// Splits the node in place and returns `(axis, mid)`, or `None` when the
+// primitives should stay together in a leaf.
+fn split_primitives(primitives: &mut [BuildPrimitive], aabb_box: &AABB) -> Option<(usize, usize)> {
+    let split = find_split(primitives, aabb_box);
+    let leaf_cost = INTERSECTION_COST * primitives.len() as f32;
+    if primitives.len() == 1
+        || (primitives.len() <= MAX_LEAF_SIZE && split.is_none_or(|(_, _, cost)| leaf_cost <= cost))
+    {
+        return None;
+    }
+
+    Some(match split {
+        Some((axis, split_bin, _)) => (axis, partition(primitives, axis, split_bin)),
+        None => (longest_axis(aabb_box), primitives.len() / 2),
+    })
+}
+
+fn build_subtree(primitives: &mut [BuildPrimitive], offset: usize) -> BuildNode {
+    let aabb_box = bounds_of(primitives);
+    let (axis, mid) = match split_primitives(primitives, &aabb_box) {
+        Some(split) => split,
+        None => {
+            return BuildNode::Leaf {
+                aabb_box,
+                start: offset,
+                count: primitives.len(),
+            }
+        }
+    };
+
+    let (left_primitives, right_primitives) = primitives.split_at_mut(mid);
+    let left = build_subtree(left_primitives, offset);
+    let right = build_subtree(right_primitives, offset + mid);
+    BuildNode::Interior {
+        aabb_box,
+        axis,
+        children: Box::new((left, right)),
+    }
+}
+
+// The top of the tree is split on the calling thread until the pieces are
+// small enough to be built independently on the pool. Splitting follows the
+// same rules as `build_subtree`, so the result does not depend on the number
+// of threads.
+fn build_parallel(
+    primitives: Vec<BuildPrimitive>,
+    pool: &ThreadPool,
+) -> (BuildNode, Vec<Option<BuildNode>>, Vec<BuildPrimitive>) {
+    let subtree_size = (primitives.len() / (8 * pool.size())).max(PARALLEL_SUBTREE_SIZE);
+    let (sender, receiver) = channel();
+    let mut jobs = 0;
+
+    let root = split_top(primitives, 0, subtree_size, pool, &sender, &mut jobs);
+    drop(sender);
+
+    let mut subtrees: Vec<Option<BuildNode>> = (0..jobs).map(|_| None).collect();
+    let mut chunks: Vec<(usize, Vec<BuildPrimitive>)> = Vec::with_capacity(jobs);
+    for _ in 0..jobs {
+        let result: SubtreeResult = receiver.recv().expect("a BVH build job panicked");
+        subtrees[result.job] = Some(result.node);
+        chunks.push((result.offset, result.primitives));
+    }
+    chunks.sort_by_key(|(offset, _)| *offset);
+
+    let ordered = chunks.into_iter().flat_map(|(_, chunk)| chunk).collect();
+    (root, subtrees, ordered)
+}
+
+fn split_top(
+    mut primitives: Vec<BuildPrimitive>,
+    offset: usize,
+    subtree_size: usize,
+    pool: &ThreadPool,
+    sender: &Sender<SubtreeResult>,
+    jobs: &mut usize,
+) -> BuildNode {
+    let aabb_box = bounds_of(&primitives);
+    let split = if primitives.len() > subtree_size {
+        split_primitives(&mut primitives, &aabb_box)
+    } else {
+        None
+    };
+
+    let (axis, mid) = match split {
+        Some(split) => split,
+        None => {
+            let job = *jobs;
+            *jobs += 1;
+            let sender = sender.clone();
+            pool.execute(move || {
+                let node = build_subtree(&mut primitives, offset);
+                let _ = sender.send(SubtreeResult {
+                    job,
+                    offset,
+                    node,
+                    primitives,
+                });
+            });
+            return BuildNode::Pending(job);
+        }
+    };
+
+    let right_primitives = primitives.split_off(mid);
+    let left = split_top(primitives, offset, subtree_size, pool, sender, jobs);
+    let right = split_top(
+        right_primitives,
+        offset + mid,
+        subtree_size,
+        pool,
+        sender,
+        jobs,
+    );
+    BuildNode::Interior {
+        aabb_box,
+        axis,
+        children: Box::new((left, right)),
+    }
+}
+
+fn flatten(node: BuildNode, depth: usize, context: &mut FlattenContext) -> usize {
+    let index = context.nodes.len();
+    context.stats.max_depth = context.stats.max_depth.max(depth);
+
+    match node {
+        BuildNode::Leaf {
+            aabb_box,
+            start,
+            count,
+        } => {
+            context.stats.leaves += 1;
+            context.stats.max_leaf_size = context.stats.max_leaf_size.max(count);
+            context.stats.sah_cost +=
+                INTERSECTION_COST * count as f32 * relative_area(&aabb_box, context.root_area);
+            context.nodes.push(LinearNode {
+                aabb_box,
+                offset: start as u32,
+                count: count as u16,
+                axis: 0,
+            });
+        }
+        BuildNode::Interior {
+            aabb_box,
+            axis,
+            children,
+        } => {
+            context.stats.interior_nodes += 1;
+            context.stats.sah_cost += TRAVERSAL_COST * relative_area(&aabb_box, context.root_area);
+            context.nodes.push(LinearNode {
+                aabb_box,
+                offset: 0,
+                count: 0,
+                axis: axis as u8,
+            });
+
+            let (left, right) = *children;
+            flatten(left, depth + 1, context);
+            let right = flatten(right, depth + 1, context);
+            context.nodes[index].offset = right as u32;
+        }
+        BuildNode::Pending(job) => {
+            let subtree = context.subtrees[job].take().unwrap();
+            return flatten(subtree, depth, context);
         }
-        let hit_left = self.left.hit(r, t_min, t_max, rec);
-        let hit_right = self
-            .right
-            .hit(r, t_min, if hit_left { rec.t } else { t_max }, rec);
-        return hit_left || hit_right;
     }
 
-    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
-        std::mem::swap(output_box, &mut self.aabb_box.clone());
-        true
+    index
+}
+
+fn longest_axis(aabb_box: &AABB) -> usize {
+    let extent = aabb_box.max() - aabb_box.min();
+    if extent.x() >= extent.y() && extent.x() >= extent.z() {
+        0
+    } else if extent.y() >= extent.z() {
+        1
+    } else {
+        2
+    }
+}
+
+fn bounds_of(primitives: &[BuildPrimitive]) -> AABB {
+    primitives
+        .iter()
+        .skip(1)
+        .fold(primitives[0].aabb_box, |acc, primitive| {
+            surrounding_box(acc, primitive.aabb_box)
+        })
+}
+
+fn centroid_bounds(primitives: &[BuildPrimitive]) -> AABB {
+    let mut min_point = primitives[0].centroid;
+    let mut max_point = primitives[0].centroid;
+    for primitive in &primitives[1..] {
+        for c in 0..3 {
+            min_point[c] = f32::min(min_point[c], primitive.centroid[c]);
+            max_point[c] = f32::max(max_point[c], primitive.centroid[c]);
+        }
+    }
+    AABB::new(&min_point, &max_point)
+}
+
+fn relative_area(aabb_box: &AABB, root_area: f32) -> f32 {
+    if root_area > 0.0 {
+        aabb_box.surface_area() / root_area
+    } else {
+        1.0
+    }
+}
+
+fn bin_index(centroid: f32, min: f32, extent: f32) -> usize {
+    (((centroid - min) / extent * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+}
+
+// Returns the cheapest (axis, first bin of the right child, cost) over all
+// three axes, or `None` when every centroid coincides.
+fn find_split(primitives: &[BuildPrimitive], aabb_box: &AABB) -> Option<(usize, usize, f32)> {
+    let centroids = centroid_bounds(primitives);
+    let area = aabb_box.surface_area();
+    let mut best: Option<(usize, usize, f32)> = None;
+
+    for axis in 0..3 {
+        let min = centroids.min()[axis];
+        let extent = centroids.max()[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut bins = [Bin {
+            count: 0,
+            aabb_box: None,
+        }; SAH_BINS];
+        for primitive in primitives {
+            let bin = &mut bins[bin_index(primitive.centroid[axis], min, extent)];
+            bin.count += 1;
+            bin.aabb_box = Some(match bin.aabb_box {
+                Some(b) => surrounding_box(b, primitive.aabb_box),
+                None => primitive.aabb_box,
+            });
+        }
+
+        let mut right_costs = [0.0; SAH_BINS];
+        let mut count = 0;
+        let mut accumulated: Option<AABB> = None;
+        for split in (1..SAH_BINS).rev() {
+            count += bins[split].count;
+            accumulated = merge(accumulated, bins[split].aabb_box);
+            right_costs[split] = count as f32 * accumulated.map_or(0.0, |b| b.surface_area());
+        }
+
+        count = 0;
+        accumulated = None;
+        for split in 1..SAH_BINS {
+            count += bins[split - 1].count;
+            accumulated = merge(accumulated, bins[split - 1].aabb_box);
+            if count == 0 || count == primitives.len() {
+                continue;
+            }
+            let left_cost = count as f32 * accumulated.map_or(0.0, |b| b.surface_area());
+            let cost = TRAVERSAL_COST
+                + INTERSECTION_COST * (left_cost + right_costs[split]) / area.max(f32::EPSILON);
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+
+    best
+}
+
+fn merge(a: Option<AABB>, b: Option<AABB>) -> Option<AABB> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(surrounding_box(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn partition(primitives: &mut [BuildPrimitive], axis: usize, split_bin: usize) -> usize {
+    let centroids = centroid_bounds(primitives);
+    let min = centroids.min()[axis];
+    let extent = centroids.max()[axis] - min;
+
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if bin_index(primitives[i].centroid[axis], min, extent) < split_bin {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        for object in &self.unbounded {
+            if object.hit(r, t_min, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+
+        let hit_tree = if self.stats.max_depth < TRAVERSAL_STACK_SIZE {
+            self.traverse(
+                r,
+                t_min,
+                closest_so_far,
+                rec,
+                &mut [0; TRAVERSAL_STACK_SIZE],
+            )
+        } else {
+            let mut stack = vec![0; self.stats.max_depth + 1];
+            self.traverse(r, t_min, closest_so_far, rec, &mut stack)
+        };
+        hit_anything || hit_tree
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        if !self.unbounded.is_empty() {
+            return false;
+        }
+        match self.nodes.first() {
+            Some(node) => {
+                *output_box = node.aabb_box;
+                true
+            }
+            None => false,
+        }
     }
 }
@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use clap::Parser;
+
+use basic_ray_tracing::{
+    color::ToneMapper,
+    output::{BitDepth, OutputFormat},
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Basic ray tracer in Rust")]
+pub struct Cli {
+    /// Built-in scene name or path to a scene file
+    #[arg(short, long, default_value = "final")]
+    pub scene: String,
+
+    /// Image width in pixels
+    #[arg(short = 'W', long, value_parser = parse_positive::<usize>)]
+    pub width: Option<usize>,
+
+    /// Image height in pixels
+    #[arg(short = 'H', long, value_parser = parse_positive::<usize>)]
+    pub height: Option<usize>,
+
+    /// Aspect ratio, either as a number or as `W:H`
+    #[arg(short, long, value_parser = parse_aspect)]
+    pub aspect: Option<f32>,
+
+    /// Samples per pixel
+    #[arg(long, value_parser = parse_positive::<i32>)]
+    pub spp: Option<i32>,
+
+    /// Maximum ray bounce depth
+    #[arg(short = 'd', long, value_parser = parse_positive::<i32>)]
+    pub max_depth: Option<i32>,
+
+    /// Number of render threads [default: number of logical CPUs]
+    #[arg(short = 'j', long, value_parser = parse_positive::<usize>)]
+    pub threads: Option<usize>,
+
+    /// Seed for scene generation and sampling; the same seed renders the same image
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Exposure adjustment in stops applied before tone mapping
+    #[arg(short, long, allow_negative_numbers = true)]
+    pub exposure: Option<f32>,
+
+    /// Tone mapping operator for 8/16-bit outputs
+    #[arg(short, long, value_parser = parse_tone_mapper)]
+    pub tone_map: Option<ToneMapper>,
+
+    /// Linear value mapped to display white [default: depends on the tone mapper]
+    #[arg(long, value_parser = parse_white_point)]
+    pub white_point: Option<f32>,
+
+    /// Output file, `-` writes to stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Output image format [default: inferred from the output extension, PNG for stdout]
+    #[arg(short, long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    /// Bits per channel: 8 or 16 for PNG/PPM, 16 (half) or 32 (float) for EXR, 32 for HDR
+    /// [default: 8, 16 for EXR, 32 for HDR]
+    #[arg(short, long, value_parser = parse_bit_depth)]
+    pub bit_depth: Option<BitDepth>,
+
+    /// Also write albedo, normal and depth layers (EXR only)
+    #[arg(long)]
+    pub layers: bool,
+
+    /// List the built-in scenes and exit
+    #[arg(long)]
+    pub list_scenes: bool,
+}
+
+fn parse_positive<T>(s: &str) -> Result<T, String>
+where
+    T: FromStr + PartialOrd + Default,
+{
+    match s.parse::<T>() {
+        Ok(value) if value > T::default() => Ok(value),
+        _ => Err(format!("expected a positive integer, got `{}`", s)),
+    }
+}
+
+fn parse_aspect(s: &str) -> Result<f32, String> {
+    let aspect = match s.split_once(':') {
+        Some((w, h)) => match (w.trim().parse::<f32>(), h.trim().parse::<f32>()) {
+            (Ok(w), Ok(h)) if h != 0.0 => w / h,
+            _ => return Err(format!("invalid aspect ratio `{}`", s)),
+        },
+        None => s
+            .parse::<f32>()
+            .map_err(|_| format!("invalid aspect ratio `{}`", s))?,
+    };
+
+    if aspect > 0.0 && aspect.is_finite() {
+        Ok(aspect)
+    } else {
+        Err(format!("aspect ratio must be positive, got `{}`", s))
+    }
+}
+
+fn parse_format(s: &str) -> Result<OutputFormat, String> {
+    OutputFormat::from_name(s).ok_or_else(|| {
+        format!(
+            "unknown format `{}`, expected one of: {}",
+            s,
+            OutputFormat::NAMES.join(", ")
+        )
+    })
+}
+
+fn parse_bit_depth(s: &str) -> Result<BitDepth, String> {
+    s.parse::<u32>()
+        .ok()
+        .and_then(BitDepth::from_bits)
+        .ok_or_else(|| format!("unsupported bit depth `{}`, expected 8, 16 or 32", s))
+}
+
+fn parse_tone_mapper(s: &str) -> Result<ToneMapper, String> {
+    ToneMapper::from_name(s).ok_or_else(|| {
+        format!(
+            "unknown tone mapper `{}`, expected one of: {}",
+            s,
+            ToneMapper::NAMES.join(", ")
+        )
+    })
+}
+
+fn parse_white_point(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!(
+            "white point must be a positive number, got `{}`",
+            s
+        )),
+    }
+}
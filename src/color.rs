@@ -1,38 +1,136 @@
-use std::{fmt::Display, num};
-
 use crate::vec3::Color;
 
-pub fn write_color(pixel_color: Color, samples_per_pixel: i32) {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Hable,
+    Aces,
+}
+
+impl ToneMapper {
+    pub const NAMES: [&'static str; 5] =
+        ["clamp", "reinhard", "extended-reinhard", "hable", "aces"];
+
+    pub fn from_name(name: &str) -> Option<ToneMapper> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "clamp" => Some(ToneMapper::Clamp),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "extended-reinhard" => Some(ToneMapper::ExtendedReinhard),
+            "hable" | "uncharted" => Some(ToneMapper::Hable),
+            "aces" => Some(ToneMapper::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn default_white_point(&self) -> Option<f32> {
+        match self {
+            ToneMapper::Clamp => Some(1.0),
+            ToneMapper::ExtendedReinhard => Some(4.0),
+            ToneMapper::Hable => Some(11.2),
+            ToneMapper::Reinhard | ToneMapper::Aces => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayTransform {
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    pub white_point: Option<f32>,
+}
+
+impl DisplayTransform {
+    pub fn default() -> DisplayTransform {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            white_point: None,
+        }
+    }
+
+    // Maps a scene-linear color to sRGB-encoded display values in [0, 1].
+    pub fn apply(&self, color: Color) -> Color {
+        let color = color * 2.0_f32.powf(self.exposure);
+        let white = self
+            .white_point
+            .or_else(|| self.tone_mapper.default_white_point());
+
+        let mapped = match self.tone_mapper {
+            ToneMapper::Clamp => color / white.unwrap_or(1.0),
+            ToneMapper::Reinhard if white.is_none() => scale_luminance(color, |l| l / (1.0 + l)),
+            // A white point turns plain Reinhard into its extended form.
+            ToneMapper::Reinhard | ToneMapper::ExtendedReinhard => {
+                let white = white.unwrap_or(1.0);
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapper::Hable => map_channels(color, hable) / hable(white.unwrap_or(1.0)),
+            ToneMapper::Aces => match white {
+                Some(white) => map_channels(color, aces) / aces(white),
+                None => map_channels(color, aces),
+            },
+        };
 
-    let scale = 1.0 / samples_per_pixel as f32;
-    r = (r * scale).sqrt();
-    g = (g * scale).sqrt();
-    b = (b * scale).sqrt();
+        map_channels(mapped, |c| srgb_oetf(c.clamp(0.0, 1.0)))
+    }
+}
+
+fn luminance(color: Color) -> f32 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
 
-    println!("{}", format_color(&Color::new(r, g, b)));
+fn scale_luminance(color: Color, curve: impl Fn(f32) -> f32) -> Color {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    color * (curve(l) / l)
 }
 
-pub fn to_color(pixel_color: Color, samples_per_pixel: i32) -> Color {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
+fn map_channels(color: Color, f: impl Fn(f32) -> f32) -> Color {
+    Color::new(f(color.x()), f(color.y()), f(color.z()))
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
 
-    let scale = 1.0 / samples_per_pixel as f32;
-    r = (r * scale).sqrt();
-    g = (g * scale).sqrt();
-    b = (b * scale).sqrt();
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+fn aces(x: f32) -> f32 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).max(0.0)
+}
+
+pub fn srgb_oetf(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_eotf(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
 
-    Color::new(r, g, b)
+pub fn quantize_u8(color: &Color) -> [u8; 3] {
+    [
+        (256.0 * color.x().clamp(0.0, 0.999)) as u8,
+        (256.0 * color.y().clamp(0.0, 0.999)) as u8,
+        (256.0 * color.z().clamp(0.0, 0.999)) as u8,
+    ]
 }
 
-pub fn format_color(color: &Color) -> String {
-    format!(
-        "{} {} {}",
-        (256.0 * color.x().clamp(0.0, 0.999)) as i32,
-        (256.0 * color.y().clamp(0.0, 0.999)) as i32,
-        (256.0 * color.z().clamp(0.0, 0.999)) as i32
-    )
+pub fn quantize_u16(color: &Color) -> [u16; 3] {
+    [
+        (65536.0 * color.x().clamp(0.0, 0.99999)) as u16,
+        (65536.0 * color.y().clamp(0.0, 0.99999)) as u16,
+        (65536.0 * color.z().clamp(0.0, 0.99999)) as u16,
+    ]
 }
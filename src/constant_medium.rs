@@ -82,6 +82,7 @@ impl Hittable for ConstantMedium {
         rec.p = r.at(rec.t);
 
         rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.shading_normal = rec.normal;
         rec.front_face = true;
         rec.mat = self.phase_function.clone();
 
@@ -117,7 +118,7 @@ impl Material for Isotropic {
         attenuation: &mut Color,
         scattered: &mut crate::ray::Ray,
     ) -> bool {
-        std::mem::swap(attenuation, &mut self.albedo.value(rec.u, rec.v, &rec.p));
+        std::mem::swap(attenuation, &mut self.albedo.value_at(rec));
         std::mem::swap(
             scattered,
             &mut (Ray::new(&rec.p, &random_in_unit_sphere(), r_in.time())),
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::{surrounding_box, AABB},
+    hittable::{HitRecord, Hittable, Interval},
+    ray::Ray,
+    vec3::Point3,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Walks the boundaries of both operands in order along the ray, tracking
+// whether the ray is inside each one, and keeps the stretches inside the
+// result. Boundaries keep the record of the operand surface they came from,
+// so every part of the result has its own normal and material.
+fn combine(operation: CsgOperation, left: &[Interval], right: &[Interval]) -> Vec<Interval> {
+    let mut boundaries: Vec<(&HitRecord, bool)> = vec![];
+    for interval in left {
+        boundaries.push((&interval.enter, true));
+        boundaries.push((&interval.exit, true));
+    }
+    for interval in right {
+        boundaries.push((&interval.enter, false));
+        boundaries.push((&interval.exit, false));
+    }
+    boundaries.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let mut output = vec![];
+    let (mut in_left, mut in_right) = (false, false);
+    let mut enter: Option<HitRecord> = None;
+    for (rec, is_left) in boundaries {
+        if is_left {
+            in_left = !in_left;
+        } else {
+            in_right = !in_right;
+        }
+
+        // Leaving the subtracted operand enters the result, so the records
+        // are re-labelled by what they mean for the result. Normals already
+        // face the ray and need no change.
+        let inside = operation.contains(in_left, in_right);
+        match enter.take() {
+            None if inside => {
+                let mut rec = rec.clone();
+                rec.front_face = true;
+                enter = Some(rec);
+            }
+            Some(start) if !inside => {
+                if rec.t > start.t {
+                    let mut exit = rec.clone();
+                    exit.front_face = false;
+                    output.push(Interval { enter: start, exit });
+                }
+            }
+            start => enter = start,
+        }
+    }
+
+    output
+}
+
+// Constructive solid geometry over two closed objects.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Csg {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    pub fn union(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOperation::Intersection, left, right)
+    }
+
+    // `left` with everything inside `right` removed.
+    pub fn difference(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOperation::Difference, left, right)
+    }
+
+    pub fn operation(&self) -> CsgOperation {
+        self.operation
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let mut intervals = vec![];
+        self.intervals(r, &mut intervals);
+
+        for boundary in intervals.iter().flat_map(|i| [&i.enter, &i.exit]) {
+            if boundary.t > t_max {
+                break;
+            }
+            if boundary.t >= t_min {
+                *rec = boundary.clone();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
+        let mut left_box = AABB::default();
+        let mut right_box = AABB::default();
+        let has_left = self.left.bounding_box(time0, time1, &mut left_box);
+        let has_right = self.right.bounding_box(time0, time1, &mut right_box);
+
+        *output_box = match (self.operation, has_left, has_right) {
+            (CsgOperation::Union, true, true) => surrounding_box(left_box, right_box),
+            (CsgOperation::Intersection, true, true) => {
+                let min_point = Point3::new(
+                    f32::max(left_box.min().x(), right_box.min().x()),
+                    f32::max(left_box.min().y(), right_box.min().y()),
+                    f32::max(left_box.min().z(), right_box.min().z()),
+                );
+                let max_point = Point3::new(
+                    f32::min(left_box.max().x(), right_box.max().x()),
+                    f32::min(left_box.max().y(), right_box.max().y()),
+                    f32::min(left_box.max().z(), right_box.max().z()),
+                );
+                AABB::new(&min_point, &max_point)
+            }
+            (CsgOperation::Intersection, false, true) => right_box,
+            (CsgOperation::Union, _, _) | (_, false, _) => return false,
+            (_, true, _) => left_box,
+        };
+        true
+    }
+
+    fn intervals(&self, r: &Ray, output: &mut Vec<Interval>) {
+        let mut left = vec![];
+        self.left.intervals(r, &mut left);
+        if left.is_empty() && self.operation != CsgOperation::Union {
+            return;
+        }
+
+        let mut right = vec![];
+        self.right.intervals(r, &mut right);
+        output.extend(combine(self.operation, &left, &right));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::vec3::{Color, Vec3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PixelAov {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f32,
+}
+
+impl PixelAov {
+    pub fn default() -> PixelAov {
+        PixelAov {
+            albedo: Color::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 0.0),
+            depth: f32::INFINITY,
+        }
+    }
+}
+
+pub struct Aovs {
+    pub albedo: Vec<Color>,
+    pub normal: Vec<Vec3>,
+    pub depth: Vec<f32>,
+}
+
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Color>,
+    pub aovs: Option<Aovs>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, with_aovs: bool) -> Framebuffer {
+        let pixels = width * height;
+        let aovs = if with_aovs {
+            Some(Aovs {
+                albedo: vec![Color::new(0.0, 0.0, 0.0); pixels],
+                normal: vec![Vec3::new(0.0, 0.0, 0.0); pixels],
+                depth: vec![f32::INFINITY; pixels],
+            })
+        } else {
+            None
+        };
+
+        Framebuffer {
+            width,
+            height,
+            color: vec![Color::new(0.0, 0.0, 0.0); pixels],
+            aovs,
+        }
+    }
+
+    // `v` counts rows from the bottom like the camera, storage is top-down.
+    fn index(&self, u: usize, v: usize) -> usize {
+        (self.height - 1 - v) * self.width + u
+    }
+
+    pub fn set_pixel(&mut self, u: usize, v: usize, color: Color, aov: Option<PixelAov>) {
+        let index = self.index(u, v);
+        self.color[index] = color;
+
+        if let (Some(aovs), Some(aov)) = (self.aovs.as_mut(), aov) {
+            aovs.albedo[index] = aov.albedo;
+            aovs.normal[index] = aov.normal;
+            aovs.depth[index] = aov.depth;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.color[y * self.width + x]
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    f32::consts::PI,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use gltf::{
+    camera::Projection, image::Format, khr_lights_punctual::Kind, mesh::Mode,
+    texture::WrappingMode, Document,
+};
+
+use crate::{
+    aabb::{surrounding_box, AABB},
+    bvh::BvhBuilder,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    instance::{shared_geometry, Instance},
+    mat4::{Mat4, Transform},
+    material::{Dielectric, DiffuseLight, Material, MetallicRoughness, NormalMapped},
+    scene::CameraSettings,
+    sphere::Sphere,
+    texture::{ImageTexture, ScaledTexture, SolidColor, Texture, TextureWrap},
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+// Punctual lights become small emissive spheres, sized relative to the
+// scene, since a path tracer can only find lights it can hit.
+const POINT_LIGHT_SIZE: f32 = 0.02;
+// Directional lights become a distant sphere of this angular radius.
+const DIRECTIONAL_LIGHT_ANGLE: f32 = 0.05;
+const DIRECTIONAL_LIGHT_DISTANCE: f32 = 100.0;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import { path: PathBuf, source: gltf::Error },
+    Invalid { path: PathBuf, message: String },
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Import { path, source } => write!(f, "{}: {}", path.display(), source),
+            GltfError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+pub struct GltfCamera {
+    pub name: Option<String>,
+    pub settings: CameraSettings,
+    pub aspect_ratio: Option<f32>,
+}
+
+pub struct GltfScene {
+    pub objects: HittableList,
+    pub cameras: Vec<GltfCamera>,
+    // Bounds of the meshes alone, leaving out the spheres standing in for
+    // lights.
+    pub bounds: Option<AABB>,
+}
+
+enum Light {
+    Point { position: Point3, intensity: Color },
+    Directional { direction: Vec3, illuminance: Color },
+}
+
+struct Importer<'a> {
+    path: &'a Path,
+    document: &'a Document,
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    bvh_builder: &'a BvhBuilder<'a>,
+    textures: HashMap<(usize, bool), Arc<dyn Texture>>,
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    meshes: HashMap<usize, Arc<dyn Hittable>>,
+    objects: HittableList,
+    cameras: Vec<GltfCamera>,
+    lights: Vec<Light>,
+    warned_spot: bool,
+}
+
+fn to_mat4(columns: [[f32; 4]; 4]) -> Mat4 {
+    Mat4::new(columns).transpose()
+}
+
+fn color(c: [f32; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+// Drops alpha and narrows every format to 8-bit RGB.
+fn rgb8(image: &gltf::image::Data) -> Vec<u8> {
+    let channels = match image.format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 | Format::R32G32B32FLOAT => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => 4,
+    };
+    let sample = |i: usize| -> u8 {
+        let p = &image.pixels;
+        match image.format {
+            Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => p[i],
+            Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => {
+                (u16::from_ne_bytes([p[2 * i], p[2 * i + 1]]) >> 8) as u8
+            }
+            Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+                let v = f32::from_ne_bytes([p[4 * i], p[4 * i + 1], p[4 * i + 2], p[4 * i + 3]]);
+                (v.clamp(0.0, 1.0) * 255.0).round() as u8
+            }
+        }
+    };
+
+    let pixel_count = (image.width * image.height) as usize;
+    let mut data = Vec::with_capacity(3 * pixel_count);
+    for pixel in 0..pixel_count {
+        let base = pixel * channels;
+        match channels {
+            1 => data.extend([sample(base); 3]),
+            2 => data.extend([sample(base), sample(base + 1), 0]),
+            _ => data.extend([sample(base), sample(base + 1), sample(base + 2)]),
+        }
+    }
+    data
+}
+
+impl<'a> Importer<'a> {
+    fn invalid(&self, message: String) -> GltfError {
+        GltfError::Invalid {
+            path: self.path.to_path_buf(),
+            message,
+        }
+    }
+
+    fn warn(&self, message: &str) {
+        eprintln!("WARNING: {}: {}", self.path.display(), message);
+    }
+
+    // Color textures are stored in sRGB; everything else is linear data.
+    fn texture(&mut self, texture: gltf::Texture, tex_coord: u32, srgb: bool) -> Arc<dyn Texture> {
+        if tex_coord != 0 {
+            self.warn(&format!(
+                "texture coordinate set {} is not supported, using set 0",
+                tex_coord
+            ));
+        }
+
+        let image_index = texture.source().index();
+        let key = (texture.index(), srgb);
+        if let Some(texture) = self.textures.get(&key) {
+            return Arc::clone(texture);
+        }
+
+        // One wrap mode serves both axes.
+        let wrap = match texture.sampler().wrap_s() {
+            WrappingMode::ClampToEdge => TextureWrap::Clamp,
+            WrappingMode::MirroredRepeat => TextureWrap::MirroredRepeat,
+            WrappingMode::Repeat => TextureWrap::Repeat,
+        };
+        let image = &self.images[image_index];
+        let result: Arc<dyn Texture> = Arc::new(ImageTexture::from_pixels(
+            image.width,
+            image.height,
+            rgb8(image),
+            wrap,
+            srgb,
+        ));
+        self.textures.insert(key, Arc::clone(&result));
+        result
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Arc<dyn Material> {
+        if let Some(result) = self.materials.get(&material.index()) {
+            return Arc::clone(result);
+        }
+
+        let result: Arc<dyn Material> = match material.transmission() {
+            Some(transmission) if transmission.transmission_factor() > 0.0 => {
+                Arc::new(Dielectric::new(material.ior().unwrap_or(1.5)))
+            }
+            _ => {
+                let pbr = material.pbr_metallic_roughness();
+                let factor = pbr.base_color_factor();
+                let factor = Color::new(factor[0], factor[1], factor[2]);
+                let base_color: Arc<dyn Texture> = match pbr.base_color_texture() {
+                    Some(info) => Arc::new(ScaledTexture::new(
+                        self.texture(info.texture(), info.tex_coord(), true),
+                        factor,
+                    )),
+                    None => Arc::new(SolidColor::new(factor)),
+                };
+                let metallic_roughness = pbr
+                    .metallic_roughness_texture()
+                    .map(|info| self.texture(info.texture(), info.tex_coord(), false));
+
+                let emissive_factor =
+                    color(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+                let emissive: Option<Arc<dyn Texture>> = if emissive_factor.near_zero() {
+                    None
+                } else {
+                    match material.emissive_texture() {
+                        Some(info) => Some(Arc::new(ScaledTexture::new(
+                            self.texture(info.texture(), info.tex_coord(), true),
+                            emissive_factor,
+                        ))),
+                        None => Some(Arc::new(SolidColor::new(emissive_factor))),
+                    }
+                };
+
+                Arc::new(MetallicRoughness::with_textures(
+                    base_color,
+                    pbr.metallic_factor(),
+                    pbr.roughness_factor(),
+                    metallic_roughness,
+                    emissive,
+                ))
+            }
+        };
+        // Tangents come from the texture coordinates of each triangle; a
+        // TANGENT attribute is not read.
+        let result: Arc<dyn Material> = match material.normal_texture() {
+            Some(normal) => Arc::new(NormalMapped::with_scale(
+                result,
+                self.texture(normal.texture(), normal.tex_coord(), false),
+                normal.scale(),
+            )),
+            None => result,
+        };
+        self.materials.insert(material.index(), Arc::clone(&result));
+        result
+    }
+
+    fn primitive(
+        &mut self,
+        primitive: &gltf::Primitive,
+    ) -> Result<Option<TriangleMesh>, GltfError> {
+        let buffers = self.buffers;
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let positions: Vec<Point3> = match reader.read_positions() {
+            Some(positions) => positions.map(|p| Point3::new(p[0], p[1], p[2])).collect(),
+            None => return Err(self.invalid("primitive has no POSITION attribute".to_string())),
+        };
+        let normals: Vec<Vec3> = reader.read_normals().map_or(vec![], |normals| {
+            normals.map(|n| Vec3::new(n[0], n[1], n[2])).collect()
+        });
+        // glTF puts the texture origin at the top left, ImageTexture at the
+        // bottom left.
+        let uvs: Vec<(f32, f32)> = reader.read_tex_coords(0).map_or(vec![], |uvs| {
+            uvs.into_f32().map(|uv| (uv[0], 1.0 - uv[1])).collect()
+        });
+        let colors: Vec<Color> = reader
+            .read_colors(0)
+            .map_or(vec![], |colors| colors.into_rgb_f32().map(color).collect());
+        for (name, len) in [
+            ("NORMAL", normals.len()),
+            ("TEXCOORD_0", uvs.len()),
+            ("COLOR_0", colors.len()),
+        ] {
+            if len != 0 && len != positions.len() {
+                return Err(self.invalid(format!(
+                    "{} has {} entries but POSITION has {}",
+                    name,
+                    len,
+                    positions.len()
+                )));
+            }
+        }
+        let vertices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let mut indices = vec![];
+        match primitive.mode() {
+            Mode::Triangles => {
+                for triangle in vertices.chunks_exact(3) {
+                    indices.push([triangle[0], triangle[1], triangle[2]]);
+                }
+            }
+            Mode::TriangleStrip => {
+                for i in 0..vertices.len().saturating_sub(2) {
+                    let odd = i % 2;
+                    indices.push([vertices[i], vertices[i + 1 + odd], vertices[i + 2 - odd]]);
+                }
+            }
+            Mode::TriangleFan => {
+                for i in 1..vertices.len().saturating_sub(1) {
+                    indices.push([vertices[i], vertices[i + 1], vertices[0]]);
+                }
+            }
+            mode => {
+                self.warn(&format!("skipping primitive drawn as {:?}", mode));
+                return Ok(None);
+            }
+        }
+        if let Some(&index) = vertices.iter().find(|&&i| i >= positions.len()) {
+            return Err(self.invalid(format!(
+                "vertex index {} out of range (have {})",
+                index,
+                positions.len()
+            )));
+        }
+
+        let mat = self.material(&primitive.material());
+        Ok(Some(TriangleMesh::with_colors(
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            mat,
+            self.bvh_builder,
+        )))
+    }
+
+    // Meshes are built once in their own space and shared by every node
+    // that uses them.
+    fn mesh(&mut self, mesh: &gltf::Mesh) -> Result<Option<Arc<dyn Hittable>>, GltfError> {
+        if let Some(geometry) = self.meshes.get(&mesh.index()) {
+            return Ok(Some(Arc::clone(geometry)));
+        }
+
+        let mut primitives = HittableList::new();
+        for primitive in mesh.primitives() {
+            if let Some(triangles) = self.primitive(&primitive)? {
+                primitives.add(Arc::new(triangles));
+            }
+        }
+        if primitives.objects.is_empty() {
+            return Ok(None);
+        }
+
+        let geometry = shared_geometry(&primitives, 0.0, 1.0, self.bvh_builder);
+        self.meshes.insert(mesh.index(), Arc::clone(&geometry));
+        Ok(Some(geometry))
+    }
+
+    fn node(&mut self, node: &gltf::Node, parent: &Mat4) -> Result<(), GltfError> {
+        let world = *parent * to_mat4(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(geometry) = self.mesh(&mesh)? {
+                match Transform::new(world) {
+                    Some(transform) => self
+                        .objects
+                        .add(Arc::new(Instance::new(geometry, transform))),
+                    None => self.warn(&format!(
+                        "skipping mesh on node {} with a singular transform",
+                        node.index()
+                    )),
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            match camera.projection() {
+                Projection::Perspective(perspective) => {
+                    // Cameras look down their local -z axis with +y up.
+                    let mut settings = CameraSettings::default();
+                    settings.look_from = world.transform_point(&Point3::new(0.0, 0.0, 0.0));
+                    settings.look_at = world.transform_point(&Point3::new(0.0, 0.0, -1.0));
+                    settings.vup =
+                        Vec3::unit_vector(&world.transform_vector(&Vec3::new(0.0, 1.0, 0.0)));
+                    settings.vfov = perspective.yfov().to_degrees();
+                    self.cameras.push(GltfCamera {
+                        name: camera.name().map(str::to_string),
+                        settings,
+                        aspect_ratio: perspective.aspect_ratio(),
+                    });
+                }
+                Projection::Orthographic(_) => self
+                    .warn("skipping orthographic camera; only perspective cameras are supported"),
+            }
+        }
+
+        if let Some(light) = node.light() {
+            let intensity = color(light.color()) * light.intensity();
+            match light.kind() {
+                Kind::Directional => self.lights.push(Light::Directional {
+                    direction: Vec3::unit_vector(
+                        &world.transform_vector(&Vec3::new(0.0, 0.0, -1.0)),
+                    ),
+                    illuminance: intensity,
+                }),
+                kind => {
+                    if matches!(kind, Kind::Spot { .. }) && !self.warned_spot {
+                        self.warn("spot lights are imported as point lights");
+                        self.warned_spot = true;
+                    }
+                    self.lights.push(Light::Point {
+                        position: world.transform_point(&Point3::new(0.0, 0.0, 0.0)),
+                        intensity,
+                    });
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, &world)?;
+        }
+        Ok(())
+    }
+
+    // Light sizes follow the extent of the geometry.
+    fn add_lights(&mut self) -> Option<AABB> {
+        let mut bounds: Option<AABB> = None;
+        let mut object_box = AABB::default();
+        for object in &self.objects.objects {
+            if object.bounding_box(0.0, 1.0, &mut object_box) {
+                bounds = Some(match bounds {
+                    Some(bounds) => surrounding_box(bounds, object_box),
+                    None => object_box,
+                });
+            }
+        }
+        let (center, radius) = match bounds {
+            Some(bounds) => (
+                0.5 * (bounds.min() + bounds.max()),
+                (0.5 * (bounds.max() - bounds.min()).length()).max(1e-3),
+            ),
+            None => (Point3::new(0.0, 0.0, 0.0), 1.0),
+        };
+
+        for light in &self.lights {
+            // A sphere of radiance L shows an area of pi r^2 in every
+            // direction, so L = I / (pi r^2) gives intensity I; a distant disc
+            // covering a solid angle of pi a^2 gives illuminance L pi a^2.
+            let (position, size, radiance) = match light {
+                Light::Point {
+                    position,
+                    intensity,
+                } => {
+                    let size = POINT_LIGHT_SIZE * radius;
+                    (*position, size, *intensity / (PI * size * size))
+                }
+                Light::Directional {
+                    direction,
+                    illuminance,
+                } => {
+                    let distance = DIRECTIONAL_LIGHT_DISTANCE * radius;
+                    (
+                        center - distance * *direction,
+                        distance * DIRECTIONAL_LIGHT_ANGLE.tan(),
+                        *illuminance / (PI * DIRECTIONAL_LIGHT_ANGLE * DIRECTIONAL_LIGHT_ANGLE),
+                    )
+                }
+            };
+            self.objects.add(Arc::new(Sphere::with_center_and_radius(
+                position,
+                size,
+                Arc::new(DiffuseLight::with_color(radiance)),
+            )));
+        }
+        bounds
+    }
+}
+
+// Imports the default scene (or the first one) of a .gltf or .glb file:
+// meshes placed by the node hierarchy, metallic-roughness materials with
+// their textures, perspective cameras and KHR_lights_punctual lights.
+pub fn load_gltf(path: &str, bvh_builder: &BvhBuilder) -> Result<GltfScene, GltfError> {
+    let path = Path::new(path);
+    let (document, buffers, images) = gltf::import(path).map_err(|source| GltfError::Import {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut importer = Importer {
+        path,
+        document: &document,
+        buffers: &buffers,
+        images: &images,
+        bvh_builder,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        objects: HittableList::new(),
+        cameras: vec![],
+        lights: vec![],
+        warned_spot: false,
+    };
+
+    let scene = match document
+        .default_scene()
+        .or_else(|| importer.document.scenes().next())
+    {
+        Some(scene) => scene,
+        None => return Err(importer.invalid("file has no scene".to_string())),
+    };
+    for node in scene.nodes() {
+        importer.node(&node, &Mat4::identity())?;
+    }
+    let bounds = importer.add_lights();
+
+    Ok(GltfScene {
+        objects: importer.objects,
+        cameras: importer.cameras,
+        bounds,
+    })
+}
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageError,
+};
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    perlin::Perlin,
+    ray::Ray,
+    triangle::intersect_triangle,
+    vec3::{Point3, Vec3},
+};
+
+// Keeps the height range of flat regions from having zero thickness.
+const PADDING: f32 = 0.0001;
+
+// Lowest and highest point of each block of 2^level x 2^level cells.
+struct MinMaxLevel {
+    width: usize,
+    depth: usize,
+    ranges: Vec<(f32, f32)>,
+}
+
+// A terrain over a regular grid of height samples, each cell split into two
+// triangles. Rays are traced through a min-max mip hierarchy over the cells,
+// so whole blocks of terrain the ray passes above or below are skipped without
+// touching their triangles.
+pub struct Heightfield {
+    origin: Point3,
+    size: Vec3,
+    samples_x: usize,
+    samples_z: usize,
+    cell_size: (f32, f32),
+    heights: Vec<f32>,
+    levels: Vec<MinMaxLevel>,
+    mat: Arc<dyn Material>,
+}
+
+impl Heightfield {
+    // `heights` holds `samples_x * samples_z` values in [0, 1], row by row
+    // along x. The terrain spans `size.x` by `size.z` from `origin`, and a
+    // height of 1 lies `size.y` above it.
+    pub fn new(
+        heights: Vec<f32>,
+        samples_x: usize,
+        samples_z: usize,
+        origin: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> Heightfield {
+        assert!(
+            samples_x >= 2 && samples_z >= 2,
+            "heightfield needs at least 2 x 2 samples"
+        );
+        assert_eq!(heights.len(), samples_x * samples_z);
+
+        let heights: Vec<f32> = heights.iter().map(|h| origin.y() + h * size.y()).collect();
+        let mut heightfield = Heightfield {
+            origin,
+            size,
+            samples_x,
+            samples_z,
+            cell_size: (
+                size.x() / (samples_x - 1) as f32,
+                size.z() / (samples_z - 1) as f32,
+            ),
+            heights,
+            levels: vec![],
+            mat,
+        };
+        heightfield.build_levels();
+        heightfield
+    }
+
+    // Brightness of a grayscale image, one sample per pixel, with the first
+    // row at `origin.z`.
+    pub fn from_image(
+        filename: &str,
+        origin: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> Result<Heightfield, ImageError> {
+        let image = image::open(filename)?.into_luma16();
+        let (width, height) = image.dimensions();
+        if width < 2 || height < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect();
+        Ok(Heightfield::new(
+            heights,
+            width as usize,
+            height as usize,
+            origin,
+            size,
+            mat,
+        ))
+    }
+
+    // Turbulence sampled over the terrain's footprint, `scale` times per unit,
+    // and stretched so the highest sample reaches `size.y`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_noise(
+        noise: &Perlin,
+        scale: f32,
+        depth: i32,
+        samples_x: usize,
+        samples_z: usize,
+        origin: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> Heightfield {
+        let mut heights = Vec::with_capacity(samples_x * samples_z);
+        for j in 0..samples_z {
+            for i in 0..samples_x {
+                let x = origin.x() + size.x() * i as f32 / (samples_x - 1) as f32;
+                let z = origin.z() + size.z() * j as f32 / (samples_z - 1) as f32;
+                heights.push(noise.turb(&Point3::new(scale * x, 0.0, scale * z), depth));
+            }
+        }
+
+        let highest = heights.iter().fold(0.0, |acc: f32, &h| acc.max(h));
+        if highest > 0.0 {
+            for h in heights.iter_mut() {
+                *h /= highest;
+            }
+        }
+        Heightfield::new(heights, samples_x, samples_z, origin, size, mat)
+    }
+
+    fn height(&self, i: usize, j: usize) -> f32 {
+        self.heights[j * self.samples_x + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        Point3::new(
+            self.origin.x() + i as f32 * self.cell_size.0,
+            self.height(i, j),
+            self.origin.z() + j as f32 * self.cell_size.1,
+        )
+    }
+
+    fn build_levels(&mut self) {
+        let width = self.samples_x - 1;
+        let depth = self.samples_z - 1;
+        let mut ranges = Vec::with_capacity(width * depth);
+        for j in 0..depth {
+            for i in 0..width {
+                let corners = [
+                    self.height(i, j),
+                    self.height(i + 1, j),
+                    self.height(i, j + 1),
+                    self.height(i + 1, j + 1),
+                ];
+                ranges.push((
+                    corners.iter().fold(f32::MAX, |acc, &h| acc.min(h)),
+                    corners.iter().fold(f32::MIN, |acc, &h| acc.max(h)),
+                ));
+            }
+        }
+        self.levels.push(MinMaxLevel {
+            width,
+            depth,
+            ranges,
+        });
+
+        loop {
+            let below = self.levels.last().unwrap();
+            if below.width == 1 && below.depth == 1 {
+                break;
+            }
+
+            let width = below.width.div_ceil(2);
+            let depth = below.depth.div_ceil(2);
+            let mut ranges = vec![(f32::MAX, f32::MIN); width * depth];
+            for j in 0..below.depth {
+                for i in 0..below.width {
+                    let (low, high) = below.ranges[j * below.width + i];
+                    let range = &mut ranges[(j / 2) * width + i / 2];
+                    range.0 = range.0.min(low);
+                    range.1 = range.1.max(high);
+                }
+            }
+            self.levels.push(MinMaxLevel {
+                width,
+                depth,
+                ranges,
+            });
+        }
+    }
+
+    // Entry distance of the ray into the block's box, if it enters before
+    // `t_max`.
+    fn block_entry(
+        &self,
+        r: &Ray,
+        inv_dir: &Vec3,
+        level: usize,
+        (i, j): (usize, usize),
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<f32> {
+        let cells = &self.levels[0];
+        let span = 1 << level;
+        let (low, high) = self.levels[level].ranges[j * self.levels[level].width + i];
+        let min_point = Point3::new(
+            self.origin.x() + (i * span) as f32 * self.cell_size.0,
+            low - PADDING,
+            self.origin.z() + (j * span) as f32 * self.cell_size.1,
+        );
+        let max_point = Point3::new(
+            self.origin.x() + ((i + 1) * span).min(cells.width) as f32 * self.cell_size.0,
+            high + PADDING,
+            self.origin.z() + ((j + 1) * span).min(cells.depth) as f32 * self.cell_size.1,
+        );
+
+        let origin = r.origin();
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for c in 0..3 {
+            let mut near = (min_point[c] - origin[c]) * inv_dir[c];
+            let mut far = (max_point[c] - origin[c]) * inv_dir[c];
+            if inv_dir[c] < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some(t0)
+    }
+
+    fn hit_triangle(
+        &self,
+        r: &Ray,
+        vertices: [Point3; 3],
+        t_min: f32,
+        t_max: &mut f32,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let (t, _, _) = match intersect_triangle(r, &vertices, t_min, *t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        // Triangles are wound either way; the terrain always faces up.
+        let edge1 = vertices[1] - vertices[0];
+        let edge2 = vertices[2] - vertices[0];
+        let mut normal = Vec3::unit_vector(&Vec3::cross(&edge1, &edge2));
+        if normal.y() < 0.0 {
+            normal = -normal;
+        }
+
+        *t_max = t;
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, &normal);
+        rec.u = (rec.p.x() - self.origin.x()) / self.size.x();
+        rec.v = 1.0 - (rec.p.z() - self.origin.z()) / self.size.z();
+        // u follows +x and v follows -z across the triangle's plane.
+        rec.tangent = self.size.x() * Vec3::new(1.0, -normal.x() / normal.y(), 0.0);
+        rec.bitangent = -self.size.z() * Vec3::new(0.0, -normal.z() / normal.y(), 1.0);
+        rec.mat = Arc::clone(&self.mat);
+        true
+    }
+
+    // Visits the blocks of the level below front to back, stopping once the
+    // next block starts beyond the closest hit found so far.
+    #[allow(clippy::too_many_arguments)]
+    fn traverse(
+        &self,
+        r: &Ray,
+        inv_dir: &Vec3,
+        level: usize,
+        (i, j): (usize, usize),
+        t_min: f32,
+        t_max: &mut f32,
+        rec: &mut HitRecord,
+    ) -> bool {
+        if level == 0 {
+            let corners = [
+                self.vertex(i, j),
+                self.vertex(i + 1, j),
+                self.vertex(i + 1, j + 1),
+                self.vertex(i, j + 1),
+            ];
+            let first =
+                self.hit_triangle(r, [corners[0], corners[1], corners[2]], t_min, t_max, rec);
+            let second =
+                self.hit_triangle(r, [corners[0], corners[2], corners[3]], t_min, t_max, rec);
+            return first || second;
+        }
+
+        let below = &self.levels[level - 1];
+        // At most four children, kept on the stack.
+        let mut children = [(0.0, (0, 0)); 4];
+        let mut count = 0;
+        for cj in 2 * j..(2 * j + 2).min(below.depth) {
+            for ci in 2 * i..(2 * i + 2).min(below.width) {
+                if let Some(entry) =
+                    self.block_entry(r, inv_dir, level - 1, (ci, cj), t_min, *t_max)
+                {
+                    children[count] = (entry, (ci, cj));
+                    count += 1;
+                }
+            }
+        }
+        let children = &mut children[..count];
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut hit_anything = false;
+        for &(entry, child) in children.iter() {
+            if entry > *t_max {
+                break;
+            }
+            if self.traverse(r, inv_dir, level - 1, child, t_min, t_max, rec) {
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let direction = r.direction();
+        let inv_dir = Vec3::new(
+            1.0 / direction.x(),
+            1.0 / direction.y(),
+            1.0 / direction.z(),
+        );
+
+        let top = self.levels.len() - 1;
+        if self
+            .block_entry(r, &inv_dir, top, (0, 0), t_min, t_max)
+            .is_none()
+        {
+            return false;
+        }
+
+        let mut closest_so_far = t_max;
+        self.traverse(r, &inv_dir, top, (0, 0), t_min, &mut closest_so_far, rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        let top = self.levels.last().unwrap();
+        let (low, high) = top.ranges[0];
+        *output_box = AABB::new(
+            &Point3::new(self.origin.x(), low - PADDING, self.origin.z()),
+            &Point3::new(
+                self.origin.x() + self.size.x(),
+                high + PADDING,
+                self.origin.z() + self.size.z(),
+            ),
+        );
+        true
+    }
+}
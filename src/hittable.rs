@@ -2,20 +2,34 @@ use std::sync::Arc;
 
 use crate::{
     aabb::AABB,
+    mat4::Transform,
     material::{EmptyMaterial, Material},
     ray::Ray,
     utils::degrees_to_radians,
-    vec3::{Point3, Vec3},
+    vec3::{Color, Point3, Vec3},
 };
+const MIN_SHADING_COS: f32 = 1e-3;
+
 #[derive(Debug, Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    // The true surface normal, facing the incoming ray.
     pub normal: Vec3,
+    // The normal materials shade with, such as one interpolated from vertex
+    // normals. It is on the same side as `normal`.
+    pub shading_normal: Vec3,
+    // Surface derivatives along u and v, not normalized. Zero where the
+    // surface has no texture frame.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub t: f32,
     pub front_face: bool,
     pub mat: Arc<dyn Material>,
     pub u: f32,
     pub v: f32,
+    pub barycentric: (f32, f32),
+    // Interpolated vertex color, for meshes that have them.
+    pub vertex_color: Option<Color>,
 }
 
 impl HitRecord {
@@ -23,27 +37,113 @@ impl HitRecord {
         HitRecord {
             p: Point3::new(0.0, 0.0, 0.0),
             normal: Vec3::new(1.0, 0.0, 0.0),
+            shading_normal: Vec3::new(1.0, 0.0, 0.0),
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
             t: 1.0,
             front_face: true,
             mat: Arc::new(EmptyMaterial {}),
             u: 0.0,
             v: 0.0,
+            barycentric: (0.0, 0.0),
+            vertex_color: None,
         }
     }
 
+    // Shades with the geometric normal and clears the texture frame.
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
+        self.set_face_normals(r, outward_normal, outward_normal);
+    }
+
+    // The geometric normal alone decides which side was hit.
+    pub fn set_face_normals(
+        &mut self,
+        r: &Ray,
+        outward_normal: &Vec3,
+        outward_shading_normal: &Vec3,
+    ) {
         self.front_face = Vec3::dot(&r.direction(), &outward_normal) < 0.0;
         self.normal = if self.front_face {
             *outward_normal
         } else {
             -*outward_normal
         };
+        self.tangent = Vec3::new(0.0, 0.0, 0.0);
+        self.bitangent = Vec3::new(0.0, 0.0, 0.0);
+        self.vertex_color = None;
+        self.set_shading_normal(r, outward_shading_normal);
+    }
+
+    // A shading normal facing away from the ray would scatter light through
+    // the surface, so it is tilted toward the geometric normal until the ray
+    // just sees its front.
+    pub fn set_shading_normal(&mut self, r: &Ray, outward_shading_normal: &Vec3) {
+        let mut shading_normal = if self.front_face {
+            *outward_shading_normal
+        } else {
+            -*outward_shading_normal
+        };
+
+        let incoming = -Vec3::unit_vector(&r.direction());
+        let cos_shading = Vec3::dot(&incoming, &shading_normal);
+        let cos_geometric = Vec3::dot(&incoming, &self.normal);
+        if cos_shading < MIN_SHADING_COS && cos_geometric > MIN_SHADING_COS {
+            shading_normal += (MIN_SHADING_COS - cos_shading) / cos_geometric * self.normal;
+            shading_normal = Vec3::unit_vector(&shading_normal);
+        }
+        self.shading_normal = shading_normal;
+    }
+
+    // The shading normal with its outward orientation restored.
+    pub fn outward_shading_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.shading_normal
+        } else {
+            -self.shading_normal
+        }
     }
 }
 
+// A stretch of a ray inside a closed object, with the surfaces where the
+// ray enters and leaves it.
+#[derive(Debug, Clone)]
+pub struct Interval {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool;
+
+    // Every interval along the whole line of the ray, in order, for objects
+    // that enclose a volume. Like `ConstantMedium`, the default steps through
+    // the surface hits one at a time and pairs them up as entry and exit, so
+    // overlapping parts of a group should be joined with a CSG union instead.
+    fn intervals(&self, r: &Ray, output: &mut Vec<Interval>) {
+        let mut t = -f32::MAX;
+        let mut enter = HitRecord::new();
+        let mut exit = HitRecord::new();
+        loop {
+            if !self.hit(r, t, f32::MAX, &mut enter) {
+                break;
+            }
+            if !self.hit(r, next_after(enter.t), f32::MAX, &mut exit) {
+                break;
+            }
+            t = next_after(exit.t);
+            output.push(Interval {
+                enter: enter.clone(),
+                exit: exit.clone(),
+            });
+        }
+    }
+}
+
+// Where to resume the search after a surface hit at `t`, far enough to skip
+// the same surface and any coincident edge of a neighbouring one.
+fn next_after(t: f32) -> f32 {
+    f32::max(t + 0.0001, t.next_up())
 }
 
 pub struct Translate {
@@ -69,8 +169,13 @@ impl Hittable for Translate {
         }
 
         rec.p += self.offset;
-        let rec_normal = rec.normal;
-        rec.set_face_normal(&moved_ray, &rec_normal);
+        let (rec_normal, shading_normal) = (rec.normal, rec.shading_normal);
+        let (tangent, bitangent) = (rec.tangent, rec.bitangent);
+        let vertex_color = rec.vertex_color;
+        rec.set_face_normals(&moved_ray, &rec_normal, &shading_normal);
+        rec.tangent = tangent;
+        rec.bitangent = bitangent;
+        rec.vertex_color = vertex_color;
 
         true
     }
@@ -161,17 +266,25 @@ impl Hittable for RotateY {
             return false;
         }
 
-        let mut p = rec.p.clone();
-        let mut normal = rec.normal.clone();
-
-        p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-
-        normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+        let rotate = |v: &Vec3| {
+            Vec3::new(
+                self.cos_theta * v[0] + self.sin_theta * v[2],
+                v[1],
+                -self.sin_theta * v[0] + self.cos_theta * v[2],
+            )
+        };
 
-        rec.p = p;
-        rec.set_face_normal(&rotated_ray, &normal);
+        rec.p = rotate(&rec.p);
+        let (tangent, bitangent) = (rotate(&rec.tangent), rotate(&rec.bitangent));
+        let vertex_color = rec.vertex_color;
+        rec.set_face_normals(
+            &rotated_ray,
+            &rotate(&rec.normal),
+            &rotate(&rec.shading_normal),
+        );
+        rec.tangent = tangent;
+        rec.bitangent = bitangent;
+        rec.vertex_color = vertex_color;
 
         true
     }
@@ -181,3 +294,45 @@ impl Hittable for RotateY {
         self.has_box
     }
 }
+
+pub struct Transformed {
+    ptr: Arc<dyn Hittable>,
+    transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(p: Arc<dyn Hittable>, transform: Transform) -> Transformed {
+        Transformed { ptr: p, transform }
+    }
+
+    pub fn object(&self) -> Arc<dyn Hittable> {
+        Arc::clone(&self.ptr)
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let object_ray = self.transform.ray_to_object(r);
+        if !self.ptr.hit(&object_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        self.transform.record_to_world(r, rec);
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
+        let mut object_box = AABB::default();
+        if !self.ptr.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+
+        *output_box = self.transform.bounding_box(&object_box);
+        true
+    }
+}
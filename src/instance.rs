@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::BvhBuilder,
+    hittable::{HitRecord, Hittable, Transformed},
+    hittable_list::HittableList,
+    mat4::Transform,
+    material::Material,
+    ray::Ray,
+};
+
+// A bottom-level BVH shared by every instance that refers to it. Building it
+// once and handing out `Arc` clones keeps a single copy of the geometry in
+// memory no matter how many times it is placed in the scene.
+pub fn shared_geometry(
+    objects: &HittableList,
+    time0: f32,
+    time1: f32,
+    builder: &BvhBuilder,
+) -> Arc<dyn Hittable> {
+    Arc::new(builder.build(&objects.objects, time0, time1))
+}
+
+// Places shared geometry with its own transform and, optionally, a material
+// that replaces the one the geometry was built with.
+pub struct Instance {
+    placed: Transformed,
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Instance {
+    pub fn new(geometry: Arc<dyn Hittable>, transform: Transform) -> Instance {
+        Instance {
+            placed: Transformed::new(geometry, transform),
+            material: None,
+        }
+    }
+
+    pub fn with_material(
+        geometry: Arc<dyn Hittable>,
+        transform: Transform,
+        material: Arc<dyn Material>,
+    ) -> Instance {
+        Instance {
+            placed: Transformed::new(geometry, transform),
+            material: Some(material),
+        }
+    }
+
+    pub fn geometry(&self) -> Arc<dyn Hittable> {
+        self.placed.object()
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.placed.transform()
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        if !self.placed.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+
+        if let Some(material) = &self.material {
+            rec.mat = Arc::clone(material);
+        }
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
+        self.placed.bounding_box(time0, time1, output_box)
+    }
+}
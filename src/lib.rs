@@ -0,0 +1,49 @@
+pub mod aabb;
+pub mod aarec;
+pub mod animated;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod constant_medium;
+pub mod csg;
+pub mod cube;
+pub mod framebuffer;
+pub mod gltf_loader;
+pub mod heightfield;
+pub mod hittable;
+pub mod hittable_list;
+pub mod instance;
+pub mod mat4;
+pub mod material;
+pub mod moving_sphere;
+pub mod obj_loader;
+pub mod output;
+pub mod perlin;
+pub mod planar;
+pub mod ply_loader;
+pub mod quadric;
+pub mod ray;
+pub mod renderer;
+pub mod scene;
+pub mod sdf;
+pub mod sphere;
+pub mod stl_loader;
+pub mod texture;
+pub mod thread_pool;
+pub mod torus;
+pub mod triangle;
+pub mod utils;
+pub mod vec3;
+
+pub use crate::{
+    bvh::BvhNode,
+    camera::Camera,
+    framebuffer::Framebuffer,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    material::Material,
+    renderer::{CancelHandle, RenderError, RenderProgress, Renderer},
+    scene::{RenderSettings, Scene},
+    texture::Texture,
+    vec3::{Color, Point3, Vec3},
+};
@@ -0,0 +1,434 @@
+use std::ops;
+
+use crate::{
+    aabb::AABB,
+    hittable::HitRecord,
+    ray::Ray,
+    utils::degrees_to_radians,
+    vec3::{Point3, Vec3},
+};
+
+// Row-major, acting on column vectors: `p' = M * p`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: &Vec3) -> Mat4 {
+        let mut result = Mat4::identity();
+        for c in 0..3 {
+            result.m[c][3] = offset[c];
+        }
+        result
+    }
+
+    pub fn scaling(factors: &Vec3) -> Mat4 {
+        let mut result = Mat4::identity();
+        for c in 0..3 {
+            result.m[c][c] = factors[c];
+        }
+        result
+    }
+
+    pub fn rotation_x(angle: f32) -> Mat4 {
+        let (sin_theta, cos_theta) = degrees_to_radians(angle).sin_cos();
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos_theta, -sin_theta, 0.0],
+            [0.0, sin_theta, cos_theta, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_y(angle: f32) -> Mat4 {
+        let (sin_theta, cos_theta) = degrees_to_radians(angle).sin_cos();
+        Mat4::new([
+            [cos_theta, 0.0, sin_theta, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin_theta, 0.0, cos_theta, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_z(angle: f32) -> Mat4 {
+        let (sin_theta, cos_theta) = degrees_to_radians(angle).sin_cos();
+        Mat4::new([
+            [cos_theta, -sin_theta, 0.0, 0.0],
+            [sin_theta, cos_theta, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation(axis: &Vec3, angle: f32) -> Mat4 {
+        Mat4::from_quat(&Quat::from_axis_angle(axis, angle))
+    }
+
+    // Rotates about x, then y, then z.
+    pub fn euler(angles: &Vec3) -> Mat4 {
+        Mat4::rotation_z(angles.z()) * Mat4::rotation_y(angles.y()) * Mat4::rotation_x(angles.x())
+    }
+
+    pub fn from_quat(q: &Quat) -> Mat4 {
+        let q = q.normalized();
+        let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+        Mat4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Places an object at `from` with its local +z axis pointing at `to`
+    // and its local +y axis as close to `up` as possible.
+    pub fn look_at(from: &Point3, to: &Point3, up: &Vec3) -> Mat4 {
+        let w = Vec3::unit_vector(&(*to - *from));
+        let u = Vec3::unit_vector(&Vec3::cross(up, &w));
+        let v = Vec3::cross(&w, &u);
+
+        let mut result = Mat4::identity();
+        for c in 0..3 {
+            result.m[c][0] = u[c];
+            result.m[c][1] = v[c];
+            result.m[c][2] = w[c];
+            result.m[c][3] = from[c];
+        }
+        result
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = Mat4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = self.m[j][i];
+            }
+        }
+        result
+    }
+
+    pub fn inverse(&self) -> Option<Mat4> {
+        // Gauss-Jordan elimination with partial pivoting, in f64 so that
+        // round trips through instance transforms stay tight.
+        let mut a = [[0.0f64; 8]; 4];
+        for (i, row) in a.iter_mut().enumerate() {
+            for (value, m) in row.iter_mut().zip(self.m[i]) {
+                *value = m as f64;
+            }
+            row[4 + i] = 1.0;
+        }
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+
+            let inv_pivot = 1.0 / a[col][col];
+            for value in a[col].iter_mut() {
+                *value *= inv_pivot;
+            }
+            let pivot_row = a[col];
+            for (i, row) in a.iter_mut().enumerate() {
+                let factor = row[col];
+                if i != col && factor != 0.0 {
+                    for (value, p) in row.iter_mut().zip(pivot_row) {
+                        *value -= factor * p;
+                    }
+                }
+            }
+        }
+
+        let mut result = Mat4::identity();
+        for (row, inverse) in result.m.iter_mut().zip(a) {
+            for (value, inv) in row.iter_mut().zip(&inverse[4..]) {
+                *value = *inv as f32;
+            }
+        }
+        Some(result)
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x, y, z) / w
+        }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Normals transform by the inverse transpose, so this is meant to be
+    // called on the inverse of the matrix that moved the surface.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+
+    // Arvo's method: the exact bounds of the transformed box, without
+    // transforming all eight corners.
+    pub fn transform_box(&self, aabb_box: &AABB) -> AABB {
+        let mut min_point = Point3::default();
+        let mut max_point = Point3::default();
+        for i in 0..3 {
+            min_point[i] = self.m[i][3];
+            max_point[i] = self.m[i][3];
+            for j in 0..3 {
+                let a = self.m[i][j] * aabb_box.min()[j];
+                let b = self.m[i][j] * aabb_box.max()[j];
+                min_point[i] += f32::min(a, b);
+                max_point[i] += f32::max(a, b);
+            }
+        }
+        AABB::new(&min_point, &max_point)
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut result = Mat4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quat {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Quat {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn from_axis_angle(axis: &Vec3, angle: f32) -> Quat {
+        let axis = Vec3::unit_vector(axis);
+        let (sin_half, cos_half) = (0.5 * degrees_to_radians(angle)).sin_cos();
+        Quat::new(
+            axis.x() * sin_half,
+            axis.y() * sin_half,
+            axis.z() * sin_half,
+            cos_half,
+        )
+    }
+
+    // Rotates about x, then y, then z.
+    pub fn from_euler(angles: &Vec3) -> Quat {
+        Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), angles.z())
+            * Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), angles.y())
+            * Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), angles.x())
+    }
+
+    pub fn length(&self) -> f32 {
+        Quat::dot(self, self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let length = self.length();
+        Quat::new(
+            self.x / length,
+            self.y / length,
+            self.z / length,
+            self.w / length,
+        )
+    }
+
+    pub fn dot(a: &Quat, b: &Quat) -> f32 {
+        a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w
+    }
+
+    // Angle in radians of the rotation taking `a` to `b`.
+    pub fn angle_between(a: &Quat, b: &Quat) -> f32 {
+        let cos_half = Quat::dot(&a.normalized(), &b.normalized()).abs();
+        2.0 * cos_half.min(1.0).acos()
+    }
+
+    // Spherical linear interpolation along the shorter arc.
+    pub fn slerp(a: &Quat, b: &Quat, t: f32) -> Quat {
+        let a = a.normalized();
+        let mut b = b.normalized();
+        let mut cos_theta = Quat::dot(&a, &b);
+        if cos_theta < 0.0 {
+            b = Quat::new(-b.x, -b.y, -b.z, -b.w);
+            cos_theta = -cos_theta;
+        }
+
+        let (wa, wb) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+
+        Quat::new(
+            wa * a.x + wb * b.x,
+            wa * a.y + wb * b.y,
+            wa * a.z + wb * b.z,
+            wa * a.w + wb * b.w,
+        )
+        .normalized()
+    }
+}
+
+impl ops::Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    // `matrix` must be affine, with a last row of [0, 0, 0, 1]: vectors,
+    // normals and bounding boxes ignore the projective part. Returns `None`
+    // when it cannot be inverted.
+    pub fn new(matrix: Mat4) -> Option<Transform> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    // Scale, then rotate, then translate. The inverse is assembled from the
+    // parts, which is cheap enough to do for every ray.
+    pub fn from_parts(translation: &Vec3, rotation: &Quat, scale: &Vec3) -> Transform {
+        let rotation = Mat4::from_quat(rotation);
+        let inverse_scale = Vec3::new(1.0 / scale.x(), 1.0 / scale.y(), 1.0 / scale.z());
+
+        Transform {
+            matrix: Mat4::translation(translation) * rotation * Mat4::scaling(scale),
+            inverse: Mat4::scaling(&inverse_scale)
+                * rotation.transpose()
+                * Mat4::translation(&-*translation),
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn inverse(&self) -> Mat4 {
+        self.inverse
+    }
+
+    // The direction is left unnormalized so that `t` means the same thing
+    // in both spaces.
+    pub fn ray_to_object(&self, r: &Ray) -> Ray {
+        Ray::new(
+            &self.inverse.transform_point(&r.origin()),
+            &self.inverse.transform_vector(&r.direction()),
+            r.time(),
+        )
+    }
+
+    pub fn record_to_world(&self, r: &Ray, rec: &mut HitRecord) {
+        let outward_normal = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let normal = self.inverse.transform_normal(&outward_normal);
+        let shading_normal = self.inverse.transform_normal(&rec.outward_shading_normal());
+        let tangent = self.matrix.transform_vector(&rec.tangent);
+        let bitangent = self.matrix.transform_vector(&rec.bitangent);
+        let vertex_color = rec.vertex_color;
+
+        rec.p = r.at(rec.t);
+        rec.set_face_normals(
+            r,
+            &Vec3::unit_vector(&normal),
+            &Vec3::unit_vector(&shading_normal),
+        );
+        rec.tangent = tangent;
+        rec.bitangent = bitangent;
+        rec.vertex_color = vertex_color;
+    }
+
+    pub fn bounding_box(&self, object_box: &AABB) -> AABB {
+        self.matrix.transform_box(object_box)
+    }
+}
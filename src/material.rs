@@ -23,6 +23,27 @@ pub trait Material: Debug + Send + Sync {
         Color::new(0.0, 0.0, 0.0)
     }
 }
+// A cosine-weighted bounce around the shading normal.
+fn diffuse_direction(rec: &HitRecord) -> Vec3 {
+    let mut direction = rec.shading_normal + random_unit_vector();
+    if direction.near_zero() {
+        direction = rec.shading_normal;
+    }
+    keep_side(direction, rec, true)
+}
+
+// A tilted shading normal can send a ray to the wrong side of the actual
+// surface, letting light leak through it. Such rays are mirrored across the
+// geometric surface onto the side they were meant for.
+fn keep_side(direction: Vec3, rec: &HitRecord, above: bool) -> Vec3 {
+    let cos = Vec3::dot(&direction, &rec.normal);
+    if (cos > 0.0) == above || cos == 0.0 {
+        direction
+    } else {
+        direction - 2.0 * cos * rec.normal
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EmptyMaterial {}
 
@@ -59,17 +80,13 @@ impl Material for Lambertian {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let mut scatter_direction = rec.normal + random_unit_vector();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
+        let scatter_direction = diffuse_direction(rec);
 
         std::mem::swap(
             scattered,
             &mut Ray::new(&rec.p, &scatter_direction, r_in.time()),
         );
-        std::mem::swap(attenuation, &mut self.albedo.value(rec.u, rec.v, &rec.p));
+        std::mem::swap(attenuation, &mut self.albedo.value_at(rec));
         true
     }
 }
@@ -96,7 +113,11 @@ impl Material for Metal {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        let reflected = reflect(&Vec3::unit_vector(&r_in.direction()), &rec.normal);
+        let reflected = keep_side(
+            reflect(&Vec3::unit_vector(&r_in.direction()), &rec.shading_normal),
+            rec,
+            true,
+        );
         std::mem::swap(
             scattered,
             &mut Ray::new(
@@ -147,18 +168,19 @@ impl Material for Dielectric {
 
         let unit_direction = Vec3::unit_vector(&r_in.direction());
 
-        let cos_theta = f32::min(Vec3::dot(&-unit_direction, &rec.normal), 1.0);
+        let cos_theta = f32::min(Vec3::dot(&-unit_direction, &rec.shading_normal), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f32()
-        {
-            reflect(&unit_direction, &rec.normal)
+        let reflects =
+            cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f32();
+        let direction = if reflects {
+            reflect(&unit_direction, &rec.shading_normal)
         } else {
-            refract(&unit_direction, &rec.normal, refraction_ratio)
+            refract(&unit_direction, &rec.shading_normal, refraction_ratio)
         };
+        let direction = keep_side(direction, rec, reflects);
 
         std::mem::swap(scattered, &mut Ray::new(&rec.p, &direction, r_in.time()));
         true
@@ -196,3 +218,162 @@ impl Material for DiffuseLight {
         self.emit.value(u, v, p)
     }
 }
+
+// The glTF metallic-roughness model. Metals reflect tinted by the base color;
+// dielectrics put a white Schlick-weighted (F0 = 0.04) specular coat over a
+// diffuse base. Roughness blurs both reflections like `Metal`'s fuzz. The
+// optional texture holds roughness in green and metalness in blue, scaling
+// the constant factors.
+#[derive(Debug)]
+pub struct MetallicRoughness {
+    base_color: Arc<dyn Texture>,
+    metallic: f32,
+    roughness: f32,
+    metallic_roughness: Option<Arc<dyn Texture>>,
+    emissive: Option<Arc<dyn Texture>>,
+}
+
+impl MetallicRoughness {
+    pub fn new(base_color: Arc<dyn Texture>, metallic: f32, roughness: f32) -> MetallicRoughness {
+        MetallicRoughness::with_textures(base_color, metallic, roughness, None, None)
+    }
+
+    pub fn with_textures(
+        base_color: Arc<dyn Texture>,
+        metallic: f32,
+        roughness: f32,
+        metallic_roughness: Option<Arc<dyn Texture>>,
+        emissive: Option<Arc<dyn Texture>>,
+    ) -> MetallicRoughness {
+        MetallicRoughness {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            metallic_roughness,
+            emissive,
+        }
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        // Vertex colors multiply the base color.
+        let mut base_color = self.base_color.value_at(rec);
+        if let Some(color) = rec.vertex_color {
+            base_color *= color;
+        }
+        let (mut metallic, mut roughness) = (self.metallic, self.roughness);
+        if let Some(texture) = &self.metallic_roughness {
+            let sample = texture.value_at(rec);
+            roughness *= sample.y();
+            metallic *= sample.z();
+        }
+        // Perceptual roughness is squared, as in the usual GGX alpha.
+        let fuzz = roughness * roughness;
+
+        let unit_direction = Vec3::unit_vector(&r_in.direction());
+        let cos_theta = f32::min(Vec3::dot(&-unit_direction, &rec.shading_normal), 1.0);
+        let specular = if random_f32() < metallic {
+            Some(base_color)
+        } else if Dielectric::reflectance(cos_theta, 1.5) > random_f32() {
+            Some(Color::new(1.0, 1.0, 1.0))
+        } else {
+            None
+        };
+
+        let (direction, color) = match specular {
+            Some(color) => (
+                keep_side(reflect(&unit_direction, &rec.shading_normal), rec, true)
+                    + fuzz * random_in_unit_sphere(),
+                color,
+            ),
+            None => (diffuse_direction(rec), base_color),
+        };
+
+        *scattered = Ray::new(&rec.p, &direction, r_in.time());
+        *attenuation = color;
+        Vec3::dot(&direction, &rec.normal) > 0.0
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Point3) -> Color {
+        match &self.emissive {
+            Some(texture) => texture.value(u, v, p),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Bends the shading normal with a tangent-space normal map before passing
+// the hit on to another material. The red and green channels tilt the
+// normal along the texture's u and v directions and blue holds its height,
+// each mapped from [0, 1] to [-1, 1]; `scale` weakens or exaggerates the
+// tilt. Surfaces without a texture frame are shaded as they are.
+#[derive(Debug)]
+pub struct NormalMapped {
+    material: Arc<dyn Material>,
+    normal_map: Arc<dyn Texture>,
+    scale: f32,
+}
+
+impl NormalMapped {
+    pub fn new(material: Arc<dyn Material>, normal_map: Arc<dyn Texture>) -> NormalMapped {
+        NormalMapped::with_scale(material, normal_map, 1.0)
+    }
+
+    pub fn with_scale(
+        material: Arc<dyn Material>,
+        normal_map: Arc<dyn Texture>,
+        scale: f32,
+    ) -> NormalMapped {
+        NormalMapped {
+            material,
+            normal_map,
+            scale,
+        }
+    }
+
+    fn apply(&self, r_in: &Ray, rec: &mut HitRecord) {
+        let n = rec.outward_shading_normal();
+        let tangent = rec.tangent - Vec3::dot(&rec.tangent, &n) * n;
+        if tangent.length_squared() < 1e-16 {
+            return;
+        }
+        let tangent = Vec3::unit_vector(&tangent);
+        let mut bitangent = Vec3::cross(&n, &tangent);
+        // Mirrored texture coordinates flip the frame.
+        if Vec3::dot(&bitangent, &rec.bitangent) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        let sample = 2.0 * self.normal_map.value_at(rec) - Color::new(1.0, 1.0, 1.0);
+        let mapped = self.scale * (sample.x() * tangent + sample.y() * bitangent) + sample.z() * n;
+        if mapped.near_zero() {
+            return;
+        }
+        rec.set_shading_normal(r_in, &Vec3::unit_vector(&mapped));
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let mut mapped = rec.clone();
+        self.apply(r_in, &mut mapped);
+        self.material.scatter(r_in, &mapped, attenuation, scattered)
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.material.emitted(u, v, p)
+    }
+}
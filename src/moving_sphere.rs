@@ -78,6 +78,8 @@ impl Hittable for MovingSphere {
         rec.p = r.at(rec.t);
         let outward_normal = (rec.p - self.center(r.time())) / self.radius();
         rec.set_face_normal(r, &outward_normal);
+        Sphere::get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+        (rec.tangent, rec.bitangent) = Sphere::get_sphere_frame(&outward_normal, self.radius());
         rec.mat = Arc::clone(&self.mat());
 
         true
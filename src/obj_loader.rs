@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::ImageError;
+
+use crate::{
+    bvh::BvhBuilder,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    texture::ImageTexture,
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Texture {
+        path: PathBuf,
+        source: ImageError,
+    },
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Texture { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f32,
+    ni: f32,
+    d: f32,
+    illum: i32,
+    map_kd: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new() -> MtlMaterial {
+        MtlMaterial {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::new(0.0, 0.0, 0.0),
+            ke: Color::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    fn to_material(&self) -> Result<Arc<dyn Material>, ObjError> {
+        let max_component = |c: &Color| f32::max(c.x(), f32::max(c.y(), c.z()));
+
+        if max_component(&self.ke) > 0.0 {
+            return Ok(Arc::new(DiffuseLight::with_color(self.ke)));
+        }
+
+        if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            return Ok(Arc::new(Dielectric::new(self.ni)));
+        }
+
+        let is_metal = matches!(self.illum, 3 | 5 | 8)
+            || (max_component(&self.kd) <= 0.0 && max_component(&self.ks) > 0.0);
+        if is_metal {
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt().clamp(0.0, 1.0);
+            return Ok(Arc::new(Metal::new(&self.ks, fuzz)));
+        }
+
+        match &self.map_kd {
+            Some(path) => {
+                let texture =
+                    ImageTexture::load(&path.to_string_lossy(), true).map_err(|source| {
+                        ObjError::Texture {
+                            path: path.clone(),
+                            source,
+                        }
+                    })?;
+                Ok(Arc::new(Lambertian::new(Arc::new(texture))))
+            }
+            None => Ok(Arc::new(Lambertian::with_color(&self.kd))),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+    indices: Vec<[usize; 3]>,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    missing_normals: bool,
+}
+
+impl MeshBuilder {
+    fn vertex(
+        &mut self,
+        key: (usize, Option<usize>, Option<usize>),
+        positions: &[Point3],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(index) = self.vertex_map.get(&key) {
+            return *index;
+        }
+
+        let (p, vt, vn) = key;
+        let index = self.positions.len();
+        self.positions.push(positions[p]);
+        // Without any `vt` in the file the mesh falls back to barycentric
+        // coordinates, which keep a usable tangent frame.
+        if !uvs.is_empty() {
+            self.uvs.push(vt.map_or((0.0, 0.0), |vt| uvs[vt]));
+        }
+        match vn {
+            Some(vn) => self.normals.push(normals[vn]),
+            None => self.missing_normals = true,
+        }
+        self.vertex_map.insert(key, index);
+        index
+    }
+
+    fn build(self, mat: Arc<dyn Material>, bvh_builder: &BvhBuilder) -> TriangleMesh {
+        let normals = if self.missing_normals {
+            vec![]
+        } else {
+            self.normals
+        };
+        TriangleMesh::new(
+            self.positions,
+            normals,
+            self.uvs,
+            self.indices,
+            mat,
+            bvh_builder,
+        )
+    }
+}
+
+struct LineParser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> LineParser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats(&self, args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, ObjError> {
+        if args.len() < min || args.len() > max {
+            return Err(self.error(format!(
+                "expected {} to {} numbers, found {}",
+                min,
+                max,
+                args.len()
+            )));
+        }
+        args.iter()
+            .map(|arg| {
+                arg.parse::<f32>()
+                    .map_err(|_| self.error(format!("invalid number `{}`", arg)))
+            })
+            .collect()
+    }
+
+    fn color(&self, args: &[&str]) -> Result<Color, ObjError> {
+        match self.floats(args, 1, 3)?[..] {
+            [c] => Ok(Color::new(c, c, c)),
+            [r, g, b] => Ok(Color::new(r, g, b)),
+            _ => Err(self.error("expected 1 or 3 color components".to_string())),
+        }
+    }
+
+    fn index(&self, token: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let value = token
+            .parse::<i64>()
+            .map_err(|_| self.error(format!("invalid {} index `{}`", kind, token)))?;
+        let resolved = match value {
+            0 => None,
+            v if v > 0 => Some(v as usize - 1),
+            v => (count as i64 + v).try_into().ok(),
+        };
+        match resolved {
+            Some(index) if index < count => Ok(index),
+            _ => Err(self.error(format!(
+                "{} index {} out of range (have {})",
+                kind, value, count
+            ))),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let parser = LineParser {
+            path,
+            line: line_index + 1,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, _)) if keyword.starts_with('#') => continue,
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(parser.error("newmtl without a name".to_string()));
+            }
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            current = Some((args.join(" "), MtlMaterial::new()));
+            continue;
+        }
+
+        let mtl = match current.as_mut() {
+            Some((_, mtl)) => mtl,
+            None => return Err(parser.error(format!("`{}` before any newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => mtl.kd = parser.color(args)?,
+            "Ks" => mtl.ks = parser.color(args)?,
+            "Ke" => mtl.ke = parser.color(args)?,
+            "Ns" => mtl.ns = parser.floats(args, 1, 1)?[0],
+            "Ni" => mtl.ni = parser.floats(args, 1, 1)?[0],
+            "d" => mtl.d = parser.floats(args, 1, 1)?[0],
+            "Tr" => mtl.d = 1.0 - parser.floats(args, 1, 1)?[0],
+            "illum" => {
+                mtl.illum = args
+                    .first()
+                    .and_then(|arg| arg.parse().ok())
+                    .ok_or_else(|| parser.error("invalid illum model".to_string()))?
+            }
+            "map_Kd" => match args.last() {
+                Some(file) => mtl.map_kd = Some(dir.join(file)),
+                None => return Err(parser.error("map_Kd without a file name".to_string())),
+            },
+            _ => {}
+        }
+    }
+
+    if let Some((name, mtl)) = current.take() {
+        materials.insert(name, mtl);
+    }
+
+    Ok(materials)
+}
+
+pub fn load_obj(path: &str, bvh_builder: &BvhBuilder) -> Result<HittableList, ObjError> {
+    let path = Path::new(path);
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    let mut positions: Vec<Point3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<(f32, f32)> = vec![];
+
+    let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let default_material: Arc<dyn Material> =
+        Arc::new(Lambertian::with_color(&Color::new(0.73, 0.73, 0.73)));
+
+    let mut group = String::new();
+    let mut material_name: Option<String> = None;
+    let mut meshes: Vec<((String, Option<String>), MeshBuilder)> = vec![];
+
+    for (line_index, line) in source.lines().enumerate() {
+        let parser = LineParser {
+            path,
+            line: line_index + 1,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, _)) if keyword.starts_with('#') => continue,
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let v = parser.floats(args, 3, 4)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = parser.floats(args, 3, 3)?;
+                normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = parser.floats(args, 1, 3)?;
+                uvs.push((v[0], *v.get(1).unwrap_or(&0.0)));
+            }
+            "g" | "o" => group = args.join(" "),
+            "usemtl" => material_name = Some(args.join(" ")),
+            "mtllib" => {
+                for file in args {
+                    mtl_materials.extend(load_mtl(&dir.join(file))?);
+                }
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parser.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        args.len()
+                    )));
+                }
+
+                let mut keys = Vec::with_capacity(args.len());
+                for arg in args {
+                    let mut parts = arg.split('/');
+                    let p = parser.index(parts.next().unwrap_or(""), positions.len(), "vertex")?;
+                    let vt = match parts.next() {
+                        Some(token) if !token.is_empty() => {
+                            Some(parser.index(token, uvs.len(), "texture coordinate")?)
+                        }
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(token) if !token.is_empty() => {
+                            Some(parser.index(token, normals.len(), "normal")?)
+                        }
+                        _ => None,
+                    };
+                    keys.push((p, vt, vn));
+                }
+
+                let key = (group.clone(), material_name.clone());
+                let builder = match meshes.iter().position(|(k, _)| *k == key) {
+                    Some(index) => &mut meshes[index].1,
+                    None => {
+                        meshes.push((key, MeshBuilder::default()));
+                        &mut meshes.last_mut().unwrap().1
+                    }
+                };
+
+                let indices: Vec<usize> = keys
+                    .into_iter()
+                    .map(|key| builder.vertex(key, &positions, &uvs, &normals))
+                    .collect();
+                for i in 1..indices.len() - 1 {
+                    builder
+                        .indices
+                        .push([indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut objects = HittableList::new();
+    for ((_, material_name), builder) in meshes {
+        let mat = match material_name {
+            Some(name) => match materials.get(&name) {
+                Some(mat) => Arc::clone(mat),
+                None => {
+                    let mat = match mtl_materials.get(&name) {
+                        Some(mtl) => mtl.to_material()?,
+                        None => {
+                            eprintln!(
+                                "WARNING: {}: material `{}` not found, using default",
+                                path.display(),
+                                name
+                            );
+                            Arc::clone(&default_material)
+                        }
+                    };
+                    materials.insert(name, Arc::clone(&mat));
+                    mat
+                }
+            },
+            None => Arc::clone(&default_material),
+        };
+        objects.add(Arc::new(builder.build(mat, bvh_builder)));
+    }
+
+    Ok(objects)
+}
@@ -0,0 +1,273 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Cursor, Write},
+    path::Path,
+};
+
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+    WritableImage,
+};
+use image::{
+    codecs::{
+        hdr::HdrEncoder,
+        pnm::{PnmSubtype, SampleEncoding},
+    },
+    DynamicImage, ImageBuffer, ImageOutputFormat, Rgb,
+};
+
+use crate::{
+    color::{quantize_u16, quantize_u8, DisplayTransform},
+    framebuffer::Framebuffer,
+    vec3::Vec3,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ppm,
+    PpmAscii,
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Exr,
+    Hdr,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 9] = [
+        "ppm",
+        "ppm-ascii",
+        "png",
+        "jpeg",
+        "jpg",
+        "bmp",
+        "tga",
+        "exr",
+        "hdr",
+    ];
+
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "ppm-ascii" => Some(OutputFormat::PpmAscii),
+            "png" => Some(OutputFormat::Png),
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "tga" => Some(OutputFormat::Tga),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<OutputFormat> {
+        OutputFormat::from_name(Path::new(path).extension()?.to_str()?)
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, OutputFormat::Exr | OutputFormat::Hdr)
+    }
+
+    pub fn default_bit_depth(&self) -> BitDepth {
+        match self {
+            OutputFormat::Exr => BitDepth::Sixteen,
+            OutputFormat::Hdr => BitDepth::ThirtyTwo,
+            _ => BitDepth::Eight,
+        }
+    }
+
+    pub fn supports_bit_depth(&self, bit_depth: BitDepth) -> bool {
+        match bit_depth {
+            BitDepth::Eight => !self.is_hdr(),
+            BitDepth::Sixteen => matches!(
+                self,
+                OutputFormat::Png | OutputFormat::Ppm | OutputFormat::PpmAscii | OutputFormat::Exr
+            ),
+            BitDepth::ThirtyTwo => self.is_hdr(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+    ThirtyTwo,
+}
+
+impl BitDepth {
+    pub fn from_bits(bits: u32) -> Option<BitDepth> {
+        match bits {
+            8 => Some(BitDepth::Eight),
+            16 => Some(BitDepth::Sixteen),
+            32 => Some(BitDepth::ThirtyTwo),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+            BitDepth::ThirtyTwo => 32,
+        }
+    }
+}
+
+const JPEG_QUALITY: u8 = 95;
+
+pub fn write_image(
+    path: &str,
+    format: OutputFormat,
+    bit_depth: BitDepth,
+    display: &DisplayTransform,
+    framebuffer: &Framebuffer,
+) -> Result<(), Box<dyn Error>> {
+    if !format.supports_bit_depth(bit_depth) {
+        return Err(format!(
+            "{:?} output does not support {}-bit samples",
+            format,
+            bit_depth.bits()
+        )
+        .into());
+    }
+
+    let mut encoded = Cursor::new(Vec::new());
+    match format {
+        OutputFormat::Exr => write_exr(&mut encoded, bit_depth, framebuffer)?,
+        OutputFormat::Hdr => {
+            let pixels: Vec<Rgb<f32>> = framebuffer.color.iter().map(|c| Rgb(c.e)).collect();
+            HdrEncoder::new(&mut encoded).encode(&pixels, framebuffer.width, framebuffer.height)?;
+        }
+        OutputFormat::Ppm | OutputFormat::PpmAscii if bit_depth == BitDepth::Sixteen => {
+            write_ppm16(
+                &mut encoded,
+                format == OutputFormat::PpmAscii,
+                display,
+                framebuffer,
+            )?
+        }
+        _ => {
+            let output_format = match format {
+                OutputFormat::Ppm => {
+                    ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                }
+                OutputFormat::PpmAscii => {
+                    ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Ascii))
+                }
+                OutputFormat::Png => ImageOutputFormat::Png,
+                OutputFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+                OutputFormat::Bmp => ImageOutputFormat::Bmp,
+                _ => ImageOutputFormat::Tga,
+            };
+            to_image(framebuffer, bit_depth, display).write_to(&mut encoded, output_format)?;
+        }
+    }
+
+    let mut out: Box<dyn Write> = if path == "-" {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    out.write_all(encoded.get_ref())?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn to_image(
+    framebuffer: &Framebuffer,
+    bit_depth: BitDepth,
+    display: &DisplayTransform,
+) -> DynamicImage {
+    let width = framebuffer.width as u32;
+    let height = framebuffer.height as u32;
+    let pixel = |x: u32, y: u32| display.apply(framebuffer.pixel(x as usize, y as usize));
+
+    match bit_depth {
+        BitDepth::Sixteen => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_fn(width, height, |x, y| {
+                Rgb(quantize_u16(&pixel(x, y)))
+            }))
+        }
+        _ => DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+            Rgb(quantize_u8(&pixel(x, y)))
+        })),
+    }
+}
+
+// The PNM encoder of the image crate only takes 8-bit samples. 16-bit PPM
+// uses a maxval of 65535, with big-endian samples in binary files.
+fn write_ppm16(
+    out: &mut Cursor<Vec<u8>>,
+    ascii: bool,
+    display: &DisplayTransform,
+    framebuffer: &Framebuffer,
+) -> io::Result<()> {
+    let magic = if ascii { "P3" } else { "P6" };
+    write!(
+        out,
+        "{}\n{} {}\n65535\n",
+        magic, framebuffer.width, framebuffer.height
+    )?;
+    for y in 0..framebuffer.height {
+        for x in 0..framebuffer.width {
+            let [r, g, b] = quantize_u16(&display.apply(framebuffer.pixel(x, y)));
+            if ascii {
+                writeln!(out, "{} {} {}", r, g, b)?;
+            } else {
+                for sample in [r, g, b] {
+                    out.write_all(&sample.to_be_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// AOVs go into the same part as the beauty pass, using the `layer.channel`
+// naming convention most compositors understand.
+fn write_exr(
+    out: &mut Cursor<Vec<u8>>,
+    bit_depth: BitDepth,
+    framebuffer: &Framebuffer,
+) -> exr::error::UnitResult {
+    let channel = |name: &str, values: Vec<f32>| {
+        let samples = match bit_depth {
+            BitDepth::ThirtyTwo => FlatSamples::F32(values),
+            _ => FlatSamples::F16(values.into_iter().map(f16::from_f32).collect()),
+        };
+        AnyChannel::new(name, samples)
+    };
+    let component = |pixels: &[Vec3], c: usize| pixels.iter().map(|p| p[c]).collect::<Vec<f32>>();
+
+    let mut channels = SmallVec::new();
+    for (c, name) in ["R", "G", "B"].iter().enumerate() {
+        channels.push(channel(name, component(&framebuffer.color, c)));
+    }
+    if let Some(aovs) = &framebuffer.aovs {
+        for (c, name) in ["R", "G", "B"].iter().enumerate() {
+            channels.push(channel(
+                &format!("albedo.{}", name),
+                component(&aovs.albedo, c),
+            ));
+        }
+        for (c, name) in ["X", "Y", "Z"].iter().enumerate() {
+            channels.push(channel(
+                &format!("normal.{}", name),
+                component(&aovs.normal, c),
+            ));
+        }
+        channels.push(channel("depth.Z", aovs.depth.clone()));
+    }
+
+    let layer = Layer::new(
+        (framebuffer.width, framebuffer.height),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer).write().to_buffered(out)
+}
@@ -0,0 +1,264 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Boxes of flat shapes are padded so they never have zero thickness.
+const PADDING: f32 = 0.0001;
+
+// Two unit vectors completing `n` to an orthonormal basis, after
+// Duff et al., "Building an Orthonormal Basis, Revisited".
+fn tangent_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    (
+        Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+        Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+    )
+}
+
+// Distance along the ray to the plane through the point at `d` along
+// `normal`, if it lies within the interval.
+fn hit_plane(r: &Ray, normal: &Vec3, d: f32, t_min: f32, t_max: f32) -> Option<f32> {
+    let denom = Vec3::dot(normal, &r.direction());
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let t = (d - Vec3::dot(normal, &r.origin())) / denom;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some(t)
+}
+
+fn padded_box(min_point: Point3, max_point: Point3) -> AABB {
+    let mut min_point = min_point;
+    let mut max_point = max_point;
+    for c in 0..3 {
+        if max_point[c] - min_point[c] < PADDING {
+            min_point[c] -= PADDING;
+            max_point[c] += PADDING;
+        }
+    }
+    AABB::new(&min_point, &max_point)
+}
+
+pub struct Quad {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    d: f32,
+    w: Vec3,
+}
+
+impl Quad {
+    // The parallelogram spanned by the edges `u` and `v` from `origin`.
+    pub fn new(origin: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Quad {
+        let n = Vec3::cross(&u, &v);
+        let normal = Vec3::unit_vector(&n);
+
+        Quad {
+            origin,
+            u,
+            v,
+            mat,
+            normal,
+            d: Vec3::dot(&normal, &origin),
+            w: n / Vec3::dot(&n, &n),
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let t = match hit_plane(r, &self.normal, self.d, t_min, t_max) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let p = r.at(t);
+        let planar = p - self.origin;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(&planar, &self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+        rec.tangent = self.u;
+        rec.bitangent = self.v;
+        rec.mat = Arc::clone(&self.mat);
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        let corners = [
+            self.origin,
+            self.origin + self.u,
+            self.origin + self.v,
+            self.origin + self.u + self.v,
+        ];
+        let mut min_point = corners[0];
+        let mut max_point = corners[0];
+        for corner in &corners[1..] {
+            for c in 0..3 {
+                min_point[c] = f32::min(min_point[c], corner[c]);
+                max_point[c] = f32::max(max_point[c], corner[c]);
+            }
+        }
+
+        *output_box = padded_box(min_point, max_point);
+        true
+    }
+}
+
+pub struct Disk {
+    center: Point3,
+    normal: Vec3,
+    radius: f32,
+    mat: Arc<dyn Material>,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f32, mat: Arc<dyn Material>) -> Disk {
+        let normal = Vec3::unit_vector(&normal);
+        let (tangent, bitangent) = tangent_basis(&normal);
+
+        Disk {
+            center,
+            normal,
+            radius,
+            mat,
+            tangent,
+            bitangent,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let d = Vec3::dot(&self.normal, &self.center);
+        let t = match hit_plane(r, &self.normal, d, t_min, t_max) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let p = r.at(t);
+        let planar = p - self.center;
+        let distance = planar.length();
+        if distance > self.radius {
+            return false;
+        }
+
+        // u runs around the rim, v outwards from the center.
+        let phi = f32::atan2(
+            Vec3::dot(&planar, &self.bitangent),
+            Vec3::dot(&planar, &self.tangent),
+        );
+        rec.t = t;
+        rec.p = p;
+        rec.u = (phi + PI) / (2.0 * PI);
+        rec.v = distance / self.radius;
+        rec.set_face_normal(r, &self.normal);
+        // Derivatives of the point along u and v; u has none at the center.
+        rec.tangent = 2.0
+            * PI
+            * (Vec3::dot(&planar, &self.tangent) * self.bitangent
+                - Vec3::dot(&planar, &self.bitangent) * self.tangent);
+        if distance > 0.0 {
+            rec.bitangent = (self.radius / distance) * planar;
+        }
+        rec.mat = Arc::clone(&self.mat);
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        let mut extent = Vec3::default();
+        for c in 0..3 {
+            extent[c] = self.radius * (1.0 - self.normal[c] * self.normal[c]).max(0.0).sqrt();
+        }
+
+        *output_box = padded_box(self.center - extent, self.center + extent);
+        true
+    }
+}
+
+// Infinite planes have no bounding box; scenes keep them out of the BVH.
+pub struct Plane {
+    point: Point3,
+    normal: Vec3,
+    mat: Arc<dyn Material>,
+    tangent: Vec3,
+    bitangent: Vec3,
+    tile_size: f32,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, mat: Arc<dyn Material>) -> Plane {
+        Plane::with_tile_size(point, normal, 1.0, mat)
+    }
+
+    // Texture coordinates repeat every `tile_size` units along the plane.
+    pub fn with_tile_size(
+        point: Point3,
+        normal: Vec3,
+        tile_size: f32,
+        mat: Arc<dyn Material>,
+    ) -> Plane {
+        let normal = Vec3::unit_vector(&normal);
+        let (tangent, bitangent) = tangent_basis(&normal);
+
+        Plane {
+            point,
+            normal,
+            mat,
+            tangent,
+            bitangent,
+            tile_size,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let d = Vec3::dot(&self.normal, &self.point);
+        let t = match hit_plane(r, &self.normal, d, t_min, t_max) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let p = r.at(t);
+        let planar = (p - self.point) / self.tile_size;
+        rec.t = t;
+        rec.p = p;
+        rec.u = Vec3::dot(&planar, &self.tangent).rem_euclid(1.0);
+        rec.v = Vec3::dot(&planar, &self.bitangent).rem_euclid(1.0);
+        rec.set_face_normal(r, &self.normal);
+        rec.tangent = self.tile_size * self.tangent;
+        rec.bitangent = self.tile_size * self.bitangent;
+        rec.mat = Arc::clone(&self.mat);
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, _output_box: &mut AABB) -> bool {
+        false
+    }
+}
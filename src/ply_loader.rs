@@ -0,0 +1,569 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::SplitAsciiWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    bvh::BvhBuilder,
+    material::Material,
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Header {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Data {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            PlyError::Header {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            PlyError::Data { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for PlyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<ScalarType> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, ScalarType::F32 | ScalarType::F64)
+    }
+
+    // Integer color channels span the whole range of their type.
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::I8 => i8::MAX as f64,
+            ScalarType::U8 => u8::MAX as f64,
+            ScalarType::I16 => i16::MAX as f64,
+            ScalarType::U16 => u16::MAX as f64,
+            ScalarType::I32 => i32::MAX as f64,
+            ScalarType::U32 => u32::MAX as f64,
+            ScalarType::F32 | ScalarType::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+    line: usize,
+}
+
+impl Element {
+    fn property(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    // Byte offset of the first data byte.
+    body_start: usize,
+}
+
+struct HeaderParser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> HeaderParser<'a> {
+    fn error(&self, message: String) -> PlyError {
+        PlyError::Header {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn scalar_type(&self, name: &str) -> Result<ScalarType, PlyError> {
+        ScalarType::parse(name)
+            .ok_or_else(|| self.error(format!("unknown property type `{}`", name)))
+    }
+
+    fn parse(&mut self, bytes: &[u8]) -> Result<Header, PlyError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        let mut offset = 0;
+
+        loop {
+            self.line += 1;
+            let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+                Some(end) => offset + end,
+                None if self.line == 1 => {
+                    return Err(self.error("not a PLY file (missing `ply` magic)".to_string()))
+                }
+                None => return Err(self.error("header has no end_header".to_string())),
+            };
+            let line = std::str::from_utf8(&bytes[offset..end])
+                .map_err(|_| self.error("header line is not ASCII text".to_string()))?
+                .trim_end_matches('\r');
+            offset = end + 1;
+
+            if self.line == 1 {
+                if line.trim() != "ply" {
+                    return Err(self.error("not a PLY file (missing `ply` magic)".to_string()));
+                }
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let (keyword, args) = match tokens.split_first() {
+                Some((keyword, args)) => (*keyword, args),
+                None => continue,
+            };
+
+            match keyword {
+                "comment" | "obj_info" => {}
+                "format" => {
+                    if format.is_some() {
+                        return Err(self.error("duplicate format line".to_string()));
+                    }
+                    let (name, version) = match args {
+                        [name, version] => (*name, *version),
+                        _ => {
+                            return Err(self.error("expected `format <type> <version>`".to_string()))
+                        }
+                    };
+                    format = Some(match name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(self.error(format!("unknown format `{}`", name))),
+                    });
+                    if version != "1.0" {
+                        return Err(self.error(format!("unsupported PLY version `{}`", version)));
+                    }
+                }
+                "element" => {
+                    let (name, count) = match args {
+                        [name, count] => (*name, *count),
+                        _ => {
+                            return Err(self.error("expected `element <name> <count>`".to_string()))
+                        }
+                    };
+                    let count = count.parse::<usize>().map_err(|_| {
+                        self.error(format!("invalid count `{}` for element `{}`", count, name))
+                    })?;
+                    elements.push(Element {
+                        name: name.to_string(),
+                        count,
+                        properties: vec![],
+                        line: self.line,
+                    });
+                }
+                "property" => {
+                    let (kind, name) = match args {
+                        ["list", count, item, name] => (
+                            PropertyType::List {
+                                count: self.scalar_type(count)?,
+                                item: self.scalar_type(item)?,
+                            },
+                            *name,
+                        ),
+                        [ty, name] if *ty != "list" => {
+                            (PropertyType::Scalar(self.scalar_type(ty)?), *name)
+                        }
+                        _ => {
+                            return Err(self.error(
+                                "expected `property <type> <name>` or \
+                                 `property list <count type> <item type> <name>`"
+                                    .to_string(),
+                            ))
+                        }
+                    };
+                    if let PropertyType::List { count, .. } = kind {
+                        if !count.is_integer() {
+                            return Err(
+                                self.error(format!("list `{}` has a non-integer count type", name))
+                            );
+                        }
+                    }
+                    let element = match elements.last_mut() {
+                        Some(element) => element,
+                        None => {
+                            return Err(
+                                self.error(format!("property `{}` before any element", name))
+                            )
+                        }
+                    };
+                    element.properties.push(Property {
+                        name: name.to_string(),
+                        kind,
+                    });
+                }
+                "end_header" => {
+                    let format = match format {
+                        Some(format) => format,
+                        None => return Err(self.error("header has no format line".to_string())),
+                    };
+                    return Ok(Header {
+                        format,
+                        elements,
+                        body_start: offset,
+                    });
+                }
+                _ => return Err(self.error(format!("unknown header keyword `{}`", keyword))),
+            }
+        }
+    }
+}
+
+// Reads the element data one value at a time, whatever the encoding.
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        position: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| "unexpected end of data".to_string())?;
+                let value = token
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid number `{}`", token))?;
+                if ty.is_integer() && value.fract() != 0.0 {
+                    return Err(format!("expected an integer, found `{}`", token));
+                }
+                Ok(value)
+            }
+            Body::Binary {
+                bytes,
+                position,
+                big_endian,
+            } => {
+                let size = ty.size();
+                if *position + size > bytes.len() {
+                    return Err("unexpected end of data".to_string());
+                }
+                let mut raw = [0u8; 8];
+                raw[..size].copy_from_slice(&bytes[*position..*position + size]);
+                *position += size;
+                if *big_endian {
+                    raw[..size].reverse();
+                }
+                Ok(match ty {
+                    ScalarType::I8 => raw[0] as i8 as f64,
+                    ScalarType::U8 => raw[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+
+    // The values of one element instance; lists are flattened after their
+    // count.
+    fn read_element(
+        &mut self,
+        element: &Element,
+        values: &mut Vec<Vec<f64>>,
+    ) -> Result<(), String> {
+        values.resize(element.properties.len(), vec![]);
+        for (property, value) in element.properties.iter().zip(values.iter_mut()) {
+            value.clear();
+            match property.kind {
+                PropertyType::Scalar(ty) => value.push(self.read(ty)?),
+                PropertyType::List { count, item } => {
+                    let n = self.read(count)?;
+                    if n < 0.0 {
+                        return Err(format!("negative length in list `{}`", property.name));
+                    }
+                    for _ in 0..n as usize {
+                        value.push(self.read(item)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where each vertex attribute lives among the vertex element's properties.
+struct VertexLayout {
+    position: [usize; 3],
+    normal: Option<[usize; 3]>,
+    color: Option<[usize; 3]>,
+    uv: Option<[usize; 2]>,
+}
+
+impl VertexLayout {
+    fn new(element: &Element, parser: &HeaderParser) -> Result<VertexLayout, PlyError> {
+        let find = |names: &[&str]| -> Option<Vec<usize>> {
+            names.iter().map(|name| element.property(name)).collect()
+        };
+        let scalar = |index: usize| -> Result<usize, PlyError> {
+            match element.properties[index].kind {
+                PropertyType::Scalar(_) => Ok(index),
+                PropertyType::List { .. } => Err(parser.error(format!(
+                    "vertex property `{}` must not be a list",
+                    element.properties[index].name
+                ))),
+            }
+        };
+        let triple = |names: &[&str]| -> Result<Option<[usize; 3]>, PlyError> {
+            match find(names) {
+                Some(i) => Ok(Some([scalar(i[0])?, scalar(i[1])?, scalar(i[2])?])),
+                None => Ok(None),
+            }
+        };
+
+        let position = match triple(&["x", "y", "z"])? {
+            Some(position) => position,
+            None => {
+                return Err(parser.error("vertex element needs x, y and z properties".to_string()))
+            }
+        };
+        let mut uv = None;
+        for names in [["u", "v"], ["s", "t"], ["texture_u", "texture_v"]] {
+            if let Some(i) = find(&names) {
+                uv = Some([scalar(i[0])?, scalar(i[1])?]);
+                break;
+            }
+        }
+
+        Ok(VertexLayout {
+            position,
+            normal: triple(&["nx", "ny", "nz"])?,
+            color: triple(&["red", "green", "blue"])?,
+            uv,
+        })
+    }
+}
+
+// Loads a PLY mesh in any of the three encodings. Besides positions, vertex
+// normals, colors and texture coordinates are picked up when present;
+// integer colors are scaled to [0, 1]. Faces are fanned into triangles and
+// any other elements are skipped.
+pub fn load_ply(
+    path: &str,
+    mat: Arc<dyn Material>,
+    bvh_builder: &BvhBuilder,
+) -> Result<TriangleMesh, PlyError> {
+    let path = Path::new(path);
+    let bytes = fs::read(path).map_err(|source| PlyError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut parser = HeaderParser { path, line: 0 };
+    let header = parser.parse(&bytes)?;
+
+    let vertex_index = header.elements.iter().position(|e| e.name == "vertex");
+    let face_index = header.elements.iter().position(|e| e.name == "face");
+    let (vertex_index, face_index) = match (vertex_index, face_index) {
+        (Some(v), Some(f)) => (v, f),
+        (None, _) => return Err(parser.error("header has no vertex element".to_string())),
+        (_, None) => {
+            return Err(parser
+                .error("header has no face element; point clouds are not supported".to_string()))
+        }
+    };
+
+    parser.line = header.elements[vertex_index].line;
+    let layout = VertexLayout::new(&header.elements[vertex_index], &parser)?;
+
+    let face_element = &header.elements[face_index];
+    parser.line = face_element.line;
+    let face_property = match face_element
+        .property("vertex_indices")
+        .or_else(|| face_element.property("vertex_index"))
+    {
+        Some(index) => index,
+        None => {
+            return Err(parser.error("face element needs a vertex_indices property".to_string()))
+        }
+    };
+    let color_scales = match layout.color {
+        Some(color) => {
+            let scale = |i: usize| match header.elements[vertex_index].properties[i].kind {
+                PropertyType::Scalar(ty) => ty.color_scale(),
+                PropertyType::List { .. } => 1.0,
+            };
+            [scale(color[0]), scale(color[1]), scale(color[2])]
+        }
+        None => [1.0; 3],
+    };
+
+    let data_error = |message: String| PlyError::Data {
+        path: path.to_path_buf(),
+        message,
+    };
+    let data = &bytes[header.body_start..];
+    let mut body = match header.format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(data)
+                .map_err(|_| data_error("ASCII data contains non-text bytes".to_string()))?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Body::Binary {
+            bytes: data,
+            position: 0,
+            big_endian: header.format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut faces: Vec<Vec<f64>> = vec![];
+    let mut values = vec![];
+
+    for (index, element) in header.elements.iter().enumerate() {
+        for instance in 0..element.count {
+            body.read_element(element, &mut values).map_err(|message| {
+                data_error(format!("{} {}: {}", element.name, instance, message))
+            })?;
+
+            if index == vertex_index {
+                let get = |i: usize| values[i][0] as f32;
+                let [x, y, z] = layout.position;
+                positions.push(Point3::new(get(x), get(y), get(z)));
+                if let Some([x, y, z]) = layout.normal {
+                    normals.push(Vec3::new(get(x), get(y), get(z)));
+                }
+                if let Some([r, g, b]) = layout.color {
+                    colors.push(Color::new(
+                        (values[r][0] / color_scales[0]) as f32,
+                        (values[g][0] / color_scales[1]) as f32,
+                        (values[b][0] / color_scales[2]) as f32,
+                    ));
+                }
+                if let Some([u, v]) = layout.uv {
+                    uvs.push((get(u), get(v)));
+                }
+            } else if index == face_index {
+                faces.push(std::mem::take(&mut values[face_property]));
+            }
+        }
+    }
+
+    let mut indices = Vec::with_capacity(faces.len());
+    for (face, vertices) in faces.iter().enumerate() {
+        if vertices.len() < 3 {
+            return Err(data_error(format!(
+                "face {} needs at least 3 vertices, found {}",
+                face,
+                vertices.len()
+            )));
+        }
+        for &vertex in vertices {
+            if vertex < 0.0 || vertex as usize >= positions.len() {
+                return Err(data_error(format!(
+                    "face {} refers to vertex {} out of range (have {})",
+                    face,
+                    vertex,
+                    positions.len()
+                )));
+            }
+        }
+        for i in 1..vertices.len() - 1 {
+            indices.push([
+                vertices[0] as usize,
+                vertices[i] as usize,
+                vertices[i + 1] as usize,
+            ]);
+        }
+    }
+
+    Ok(TriangleMesh::with_colors(
+        positions,
+        normals,
+        uvs,
+        colors,
+        indices,
+        mat,
+        bvh_builder,
+    ))
+}
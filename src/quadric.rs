@@ -0,0 +1,378 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Roots of a x^2 + b x + c in increasing order.
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    if a.abs() < 1e-12 {
+        if b == 0.0 {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Angle around the y axis, from +x towards +z, in [0, 2pi).
+pub(crate) fn sweep_angle(p: &Vec3) -> f32 {
+    let phi = f32::atan2(p.z(), p.x());
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+// A surface of revolution about the vertical axis through `base`,
+// x^2 + z^2 = a y^2 + b y + c for 0 <= y <= height in local coordinates,
+// swept from 0 to `phi_max` around the axis. Caps close off the ends.
+struct Quadric {
+    base: Point3,
+    height: f32,
+    a: f32,
+    b: f32,
+    c: f32,
+    phi_max: f32,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Quadric {
+    fn new(
+        base: Point3,
+        height: f32,
+        (a, b, c): (f32, f32, f32),
+        sweep: f32,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Quadric {
+        Quadric {
+            base,
+            height,
+            a,
+            b,
+            c,
+            phi_max: sweep.clamp(0.0, 360.0).to_radians(),
+            capped,
+            mat,
+        }
+    }
+
+    fn radius_squared(&self, y: f32) -> f32 {
+        ((self.a * y + self.b) * y + self.c).max(0.0)
+    }
+
+    fn set_record(
+        &self,
+        r: &Ray,
+        t: f32,
+        outward_normal: &Vec3,
+        (u, v): (f32, f32),
+        (dpdu, dpdv): (Vec3, Vec3),
+        rec: &mut HitRecord,
+    ) {
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, outward_normal);
+        rec.u = u;
+        rec.v = v;
+        rec.tangent = dpdu;
+        rec.bitangent = dpdv;
+        rec.mat = Arc::clone(&self.mat);
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let o = r.origin() - self.base;
+        let d = r.direction();
+        let mut closest_so_far = t_max;
+        let mut hit_anything = false;
+
+        let qa = d.x() * d.x() + d.z() * d.z() - self.a * d.y() * d.y();
+        let qb = 2.0 * (o.x() * d.x() + o.z() * d.z() - self.a * o.y() * d.y()) - self.b * d.y();
+        let qc = o.x() * o.x() + o.z() * o.z() - self.a * o.y() * o.y() - self.b * o.y() - self.c;
+        if let Some((t0, t1)) = solve_quadratic(qa, qb, qc) {
+            for t in [t0, t1] {
+                if t < t_min || t > closest_so_far {
+                    continue;
+                }
+                let p = o + t * d;
+                let phi = sweep_angle(&p);
+                if p.y() < 0.0 || p.y() > self.height || phi > self.phi_max {
+                    continue;
+                }
+
+                let normal = Vec3::new(p.x(), -(self.a * p.y() + 0.5 * self.b), p.z());
+                let uv = (phi / self.phi_max, p.y() / self.height);
+                // The radius changes along v at d(rho^2)/dy / 2 per unit of
+                // height, pushing the point outwards.
+                let rho_squared = p.x() * p.x() + p.z() * p.z();
+                let spread = if rho_squared > 0.0 {
+                    (self.a * p.y() + 0.5 * self.b) / rho_squared
+                } else {
+                    0.0
+                };
+                let dpdu = self.phi_max * Vec3::new(-p.z(), 0.0, p.x());
+                let dpdv = self.height * Vec3::new(spread * p.x(), 1.0, spread * p.z());
+                self.set_record(r, t, &Vec3::unit_vector(&normal), uv, (dpdu, dpdv), rec);
+                closest_so_far = t;
+                hit_anything = true;
+                break;
+            }
+        }
+
+        if self.capped && d.y() != 0.0 {
+            for (y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let radius_squared = self.radius_squared(y);
+                let t = (y - o.y()) / d.y();
+                if radius_squared <= 0.0 || t < t_min || t > closest_so_far {
+                    continue;
+                }
+                let p = o + t * d;
+                let rho_squared = p.x() * p.x() + p.z() * p.z();
+                let phi = sweep_angle(&p);
+                if rho_squared > radius_squared || phi > self.phi_max {
+                    continue;
+                }
+
+                let uv = (phi / self.phi_max, (rho_squared / radius_squared).sqrt());
+                let dpdu = self.phi_max * Vec3::new(-p.z(), 0.0, p.x());
+                let dpdv = if rho_squared > 0.0 {
+                    (radius_squared / rho_squared).sqrt() * Vec3::new(p.x(), 0.0, p.z())
+                } else {
+                    Vec3::default()
+                };
+                let normal = Vec3::new(0.0, normal_y, 0.0);
+                self.set_record(r, t, &normal, uv, (dpdu, dpdv), rec);
+                closest_so_far = t;
+                hit_anything = true;
+            }
+        }
+
+        hit_anything
+    }
+
+    // The profile is convex or linear in y, so it is widest at an end.
+    fn bounding_box(&self) -> AABB {
+        let radius = f32::max(self.radius_squared(0.0), self.radius_squared(self.height)).sqrt();
+        AABB::new(
+            &(self.base + Vec3::new(-radius, 0.0, -radius)),
+            &(self.base + Vec3::new(radius, self.height, radius)),
+        )
+    }
+}
+
+pub struct Cylinder {
+    quadric: Quadric,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, radius: f32, height: f32, mat: Arc<dyn Material>) -> Cylinder {
+        Cylinder::with_options(base, radius, height, 360.0, false, mat)
+    }
+
+    pub fn with_options(
+        base: Point3,
+        radius: f32,
+        height: f32,
+        sweep: f32,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Cylinder {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "a cylinder needs a positive radius and height"
+        );
+        Cylinder {
+            quadric: Quadric::new(
+                base,
+                height,
+                (0.0, 0.0, radius * radius),
+                sweep,
+                capped,
+                mat,
+            ),
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.quadric.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.quadric.bounding_box();
+        true
+    }
+}
+
+// Base of the given radius at `base`, apex `height` above it.
+pub struct Cone {
+    quadric: Quadric,
+}
+
+impl Cone {
+    pub fn new(base: Point3, radius: f32, height: f32, mat: Arc<dyn Material>) -> Cone {
+        Cone::with_options(base, radius, height, 360.0, false, mat)
+    }
+
+    pub fn with_options(
+        base: Point3,
+        radius: f32,
+        height: f32,
+        sweep: f32,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Cone {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "a cone needs a positive radius and height"
+        );
+        let k = (radius / height) * (radius / height);
+        Cone {
+            quadric: Quadric::new(
+                base,
+                height,
+                (k, -2.0 * k * height, k * height * height),
+                sweep,
+                capped,
+                mat,
+            ),
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.quadric.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.quadric.bounding_box();
+        true
+    }
+}
+
+// Vertex at `base`, opening upwards to the given radius at `height`.
+pub struct Paraboloid {
+    quadric: Quadric,
+}
+
+impl Paraboloid {
+    pub fn new(base: Point3, radius: f32, height: f32, mat: Arc<dyn Material>) -> Paraboloid {
+        Paraboloid::with_options(base, radius, height, 360.0, false, mat)
+    }
+
+    pub fn with_options(
+        base: Point3,
+        radius: f32,
+        height: f32,
+        sweep: f32,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Paraboloid {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "a paraboloid needs a positive radius and height"
+        );
+        Paraboloid {
+            quadric: Quadric::new(
+                base,
+                height,
+                (0.0, radius * radius / height, 0.0),
+                sweep,
+                capped,
+                mat,
+            ),
+        }
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.quadric.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.quadric.bounding_box();
+        true
+    }
+}
+
+// Hyperboloid of one sheet with the given radius at both ends, narrowing
+// to `waist_radius` halfway up.
+pub struct Hyperboloid {
+    quadric: Quadric,
+}
+
+impl Hyperboloid {
+    pub fn new(
+        base: Point3,
+        radius: f32,
+        waist_radius: f32,
+        height: f32,
+        mat: Arc<dyn Material>,
+    ) -> Hyperboloid {
+        Hyperboloid::with_options(base, radius, waist_radius, height, 360.0, false, mat)
+    }
+
+    pub fn with_options(
+        base: Point3,
+        radius: f32,
+        waist_radius: f32,
+        height: f32,
+        sweep: f32,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Hyperboloid {
+        assert!(
+            radius > 0.0 && height > 0.0,
+            "a hyperboloid needs a positive radius and height"
+        );
+        assert!(
+            waist_radius <= radius,
+            "the waist of a hyperboloid cannot be wider than its ends"
+        );
+        let half_height = 0.5 * height;
+        let s = (radius * radius - waist_radius * waist_radius) / (half_height * half_height);
+        Hyperboloid {
+            quadric: Quadric::new(
+                base,
+                height,
+                (
+                    s,
+                    -s * height,
+                    waist_radius * waist_radius + s * half_height * half_height,
+                ),
+                sweep,
+                capped,
+                mat,
+            ),
+        }
+    }
+}
+
+impl Hittable for Hyperboloid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        self.quadric.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.quadric.bounding_box();
+        true
+    }
+}
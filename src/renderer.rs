@@ -0,0 +1,252 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    camera::Camera,
+    framebuffer::Framebuffer,
+    hittable::Hittable,
+    scene::RenderSettings,
+    thread_pool::ThreadPool,
+    utils::{first_hit_aov, random_f32, ray_color, seed_sample, PixelInfo},
+    vec3::Color,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderProgress {
+    pub completed_pixels: usize,
+    pub total_pixels: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Cancelled,
+    // The render threads stopped, e.g. after a panic, before every pixel
+    // was delivered.
+    WorkerFailed {
+        completed_pixels: usize,
+        total_pixels: usize,
+    },
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::Cancelled => write!(f, "render cancelled"),
+            RenderError::WorkerFailed {
+                completed_pixels,
+                total_pixels,
+            } => write!(
+                f,
+                "render workers stopped after {} of {} pixels",
+                completed_pixels, total_pixels
+            ),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Renderer {
+    world: Arc<dyn Hittable>,
+    camera: Camera,
+    settings: RenderSettings,
+    pool: Arc<ThreadPool>,
+    aovs: bool,
+    cancel: CancelHandle,
+}
+
+impl Renderer {
+    pub fn new(
+        world: Arc<dyn Hittable>,
+        camera: Camera,
+        settings: RenderSettings,
+        num_threads: usize,
+    ) -> Renderer {
+        let pool = Arc::new(ThreadPool::new(num_threads.max(1)));
+        Renderer::with_thread_pool(world, camera, settings, pool)
+    }
+
+    // Sharing the pool lets scene loading (e.g. parallel BVH builds) use the
+    // same worker threads as rendering.
+    pub fn with_thread_pool(
+        world: Arc<dyn Hittable>,
+        camera: Camera,
+        settings: RenderSettings,
+        pool: Arc<ThreadPool>,
+    ) -> Renderer {
+        Renderer {
+            world,
+            camera,
+            settings,
+            pool,
+            aovs: false,
+            cancel: CancelHandle {
+                cancelled: Arc::new(AtomicBool::new(false)),
+            },
+        }
+    }
+
+    pub fn set_aovs(&mut self, enabled: bool) {
+        self.aovs = enabled;
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.pool.size()
+    }
+
+    pub fn thread_pool(&self) -> &Arc<ThreadPool> {
+        &self.pool
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn render(&self) -> Result<Framebuffer, RenderError> {
+        self.render_with_progress(|_| {})
+    }
+
+    // The callback runs on the calling thread, roughly once per image row.
+    pub fn render_with_progress(
+        &self,
+        mut progress: impl FnMut(&RenderProgress),
+    ) -> Result<Framebuffer, RenderError> {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height();
+        let total_pixels = image_width * image_height;
+        let mut framebuffer = Framebuffer::new(image_width, image_height, self.aovs);
+
+        let (sender, receiver): (SyncSender<PixelInfo>, Receiver<PixelInfo>) = sync_channel(16);
+        let start_time = Instant::now();
+
+        thread::scope(|s| {
+            s.spawn(move || self.dispatch_rows(sender));
+
+            // Owned by the closure so workers blocked on a full channel are
+            // released before the scope joins the dispatcher.
+            let receiver = receiver;
+            let mut completed_pixels = 0;
+            while completed_pixels < total_pixels {
+                if self.cancel.is_cancelled() {
+                    return Err(RenderError::Cancelled);
+                }
+                let pixel_info = match receiver.recv_timeout(Duration::from_millis(100)) {
+                    Ok(pixel_info) => pixel_info,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) if self.cancel.is_cancelled() => {
+                        return Err(RenderError::Cancelled)
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(RenderError::WorkerFailed {
+                            completed_pixels,
+                            total_pixels,
+                        })
+                    }
+                };
+                framebuffer.set_pixel(pixel_info.u, pixel_info.v, pixel_info.color, pixel_info.aov);
+
+                completed_pixels += 1;
+                if completed_pixels % image_width == 0 || completed_pixels == total_pixels {
+                    progress(&RenderProgress {
+                        completed_pixels,
+                        total_pixels,
+                        elapsed: start_time.elapsed(),
+                    });
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(framebuffer)
+    }
+
+    fn dispatch_rows(&self, sender: SyncSender<PixelInfo>) {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height();
+        let samples_per_pixel = self.settings.samples_per_pixel;
+        let max_depth = self.settings.max_depth;
+        let background = self.settings.background;
+        let seed = self.settings.seed;
+        let cam = self.camera;
+        let with_aovs = self.aovs;
+        // A single row or column of pixels still spans the whole viewport.
+        let u_span = image_width.saturating_sub(1).max(1) as f32;
+        let v_span = image_height.saturating_sub(1).max(1) as f32;
+
+        for j in (0..image_height).rev() {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
+            let world = Arc::clone(&self.world);
+            let sender = sender.clone();
+            let cancel = self.cancel.clone();
+            self.pool.execute(move || {
+                for i in 0..image_width {
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+
+                    let pixel = (j * image_width + i) as u64;
+                    let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                    for sample in 0..samples_per_pixel {
+                        seed_sample(seed, pixel, sample as u64);
+                        let u = (i as f32 + random_f32()) / u_span;
+                        let v = (j as f32 + random_f32()) / v_span;
+                        let ray = cam.get_ray(u, v);
+                        pixel_color += ray_color(&ray, &background, world.clone(), max_depth);
+                    }
+                    pixel_color /= samples_per_pixel as f32;
+
+                    let aov = if with_aovs {
+                        seed_sample(seed, pixel, samples_per_pixel as u64);
+                        let u = (i as f32 + 0.5) / u_span;
+                        let v = (j as f32 + 0.5) / v_span;
+                        Some(first_hit_aov(&cam.get_ray(u, v), &world))
+                    } else {
+                        None
+                    };
+
+                    let pixel_info = PixelInfo {
+                        u: i,
+                        v: j,
+                        color: pixel_color,
+                        aov,
+                    };
+                    if sender.send(pixel_info).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,2042 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use toml::{
+    de::{DeTable, DeValue},
+    Spanned,
+};
+
+use crate::{
+    aabb::AABB,
+    aarec::{XYRect, XZRect, YZRect},
+    animated::{Animated, Keyframe},
+    bvh::{BvhBuilder, BvhNode, BvhStats, RefitError},
+    camera::Camera,
+    color::{DisplayTransform, ToneMapper},
+    constant_medium::ConstantMedium,
+    csg::{Csg, CsgOperation},
+    cube::Cube,
+    gltf_loader::{load_gltf, GltfError},
+    heightfield::Heightfield,
+    hittable::{Hittable, RotateY, Transformed, Translate},
+    hittable_list::HittableList,
+    instance::{shared_geometry, Instance},
+    mat4::{Mat4, Quat, Transform},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, NormalMapped},
+    moving_sphere::MovingSphere,
+    obj_loader::{load_obj, ObjError},
+    perlin::Perlin,
+    planar::{Disk, Plane, Quad},
+    ply_loader::{load_ply, PlyError},
+    quadric::{Cone, Cylinder, Hyperboloid, Paraboloid},
+    sdf::{
+        Repeat, Sdf, SdfBox, SdfCapsule, SdfObject, SdfSphere, SdfTorus, SmoothSubtraction,
+        SmoothUnion, Twist,
+    },
+    sphere::Sphere,
+    stl_loader::{load_stl, StlError},
+    texture::{
+        CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, VertexColorTexture,
+    },
+    thread_pool::ThreadPool,
+    torus::Torus,
+    triangle::{Triangle, TriangleMesh},
+    utils::{
+        cornell_box, cornell_box_smoke, hittable_list_csg, hittable_list_earth,
+        hittable_list_forest, hittable_list_obj_model, hittable_list_quads, hittable_list_scans,
+        hittable_list_sdf, hittable_list_shading_normals, hittable_list_shapes,
+        hittable_list_simple_light, hittable_list_terrain, hittable_list_triangles,
+        hittalbe_list_final_scene, random_scene, two_perlin_shpheres, two_shpheres,
+    },
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+        source_line: String,
+        width: usize,
+    },
+    Obj(ObjError),
+    Gltf(GltfError),
+    Ply(PlyError),
+    Stl(StlError),
+    UnknownScene(String),
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                message,
+                source_line,
+                width,
+            } => {
+                let gutter = " ".repeat(line.to_string().len());
+                writeln!(f, "{}:{}:{}: {}", path.display(), line, column, message)?;
+                writeln!(f, "{} |", gutter)?;
+                writeln!(f, "{} | {}", line, source_line)?;
+                write!(
+                    f,
+                    "{} | {}{}",
+                    gutter,
+                    " ".repeat(column - 1),
+                    "^".repeat(*width)
+                )
+            }
+            SceneError::Obj(err) => write!(f, "{}", err),
+            SceneError::Gltf(err) => write!(f, "{}", err),
+            SceneError::Ply(err) => write!(f, "{}", err),
+            SceneError::Stl(err) => write!(f, "{}", err),
+            SceneError::UnknownScene(name) => write!(f, "unknown built-in scene `{}`", name),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> Self {
+        SceneError::Obj(err)
+    }
+}
+
+impl From<GltfError> for SceneError {
+    fn from(err: GltfError) -> Self {
+        SceneError::Gltf(err)
+    }
+}
+
+impl From<PlyError> for SceneError {
+    fn from(err: PlyError) -> Self {
+        SceneError::Ply(err)
+    }
+}
+
+impl From<StlError> for SceneError {
+    fn from(err: StlError) -> Self {
+        SceneError::Stl(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub time0: f32,
+    pub time1: f32,
+}
+
+impl CameraSettings {
+    pub fn default() -> CameraSettings {
+        CameraSettings {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            time0: 0.0,
+            time1: 1.0,
+        }
+    }
+
+    pub fn build(&self, aspect_ratio: f32) -> Camera {
+        Camera::new(
+            &self.look_from,
+            &self.look_at,
+            &self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub aspect_ratio: f32,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    pub background: Color,
+    pub display: DisplayTransform,
+    pub seed: u64,
+}
+
+impl RenderSettings {
+    pub fn default() -> RenderSettings {
+        RenderSettings {
+            image_width: 400,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            background: Color::new(0.0, 0.0, 0.0),
+            display: DisplayTransform::default(),
+            seed: 0,
+        }
+    }
+
+    pub fn image_height(&self) -> usize {
+        ((self.image_width as f32 / self.aspect_ratio).round() as usize).max(1)
+    }
+}
+
+pub struct Scene {
+    pub world: Arc<dyn Hittable>,
+    pub bvh_stats: Option<BvhStats>,
+    // Mesh, shared geometry and group BVHs built below the top level.
+    pub nested_bvh_stats: Vec<BvhStats>,
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    // The top-level BVH behind `world` and the shutter interval it covers.
+    bvh: Arc<BvhNode>,
+    time0: f32,
+    time1: f32,
+}
+
+impl Scene {
+    // Replaces top-level objects, indexed in the order the scene lists them,
+    // and refits the top-level BVH around them. `world` is rebuilt to match;
+    // a renderer still holding the previous world keeps rendering it.
+    pub fn refit(&mut self, changes: &[(usize, Arc<dyn Hittable>)]) -> Result<(), RefitError> {
+        self.update(changes, None).map(|_| ())
+    }
+
+    // Like `refit`, but rebuilds the top-level BVH, on `pool` when given, once
+    // its SAH cost has grown beyond `max_cost_ratio` times the cost of the
+    // last full build. Returns whether it was rebuilt.
+    pub fn refit_or_rebuild(
+        &mut self,
+        changes: &[(usize, Arc<dyn Hittable>)],
+        max_cost_ratio: f32,
+        pool: Option<&ThreadPool>,
+    ) -> Result<bool, RefitError> {
+        self.update(changes, Some((max_cost_ratio, pool)))
+    }
+
+    fn update(
+        &mut self,
+        changes: &[(usize, Arc<dyn Hittable>)],
+        rebuild: Option<(f32, Option<&ThreadPool>)>,
+    ) -> Result<bool, RefitError> {
+        let (time0, time1) = (self.time0, self.time1);
+        // `world` shares the BVH; letting go of it first lets the BVH be
+        // updated in place rather than copied.
+        self.world = Arc::new(HittableList::new());
+        let bvh = Arc::make_mut(&mut self.bvh);
+        let result = match rebuild {
+            Some((max_cost_ratio, pool)) => {
+                bvh.refit_or_rebuild(changes, time0, time1, max_cost_ratio, pool)
+            }
+            None => bvh.refit(changes, time0, time1).map(|stats| (stats, false)),
+        };
+        self.world = self.bvh.clone();
+
+        let (stats, rebuilt) = result?;
+        if stats.primitives > 0 {
+            self.bvh_stats = Some(stats);
+        }
+        Ok(rebuilt)
+    }
+}
+
+pub const BUILTIN_SCENES: [(&str, &str); 19] = [
+    ("random", "Bouncing spheres from Ray Tracing in One Weekend"),
+    ("two_spheres", "Two checkered spheres"),
+    (
+        "two_perlin_spheres",
+        "Two spheres with Perlin noise texture",
+    ),
+    ("earth", "Image-textured earth"),
+    (
+        "simple_light",
+        "Perlin spheres lit by a sphere and a rectangle light",
+    ),
+    ("cornell_box", "Cornell box with two rotated boxes"),
+    ("cornell_box_smoke", "Cornell box with smoke and fog blocks"),
+    ("triangles", "Triangle mesh pyramid and a metal triangle"),
+    ("obj_model", "Wavefront OBJ model with MTL materials"),
+    (
+        "scans",
+        "Vertex-colored PLY sphere next to a metal STL prism",
+    ),
+    (
+        "gltf",
+        "glTF scene with PBR materials, a node hierarchy, a camera and a point light",
+    ),
+    (
+        "shading_normals",
+        "A faceted and a smooth-shaded coarse sphere, and normal-mapped tiles",
+    ),
+    (
+        "quads",
+        "Tilted quads, a disk and a quad light over an infinite plane",
+    ),
+    (
+        "shapes",
+        "Cylinders, a cone, a paraboloid, a hyperboloid and tori",
+    ),
+    (
+        "csg",
+        "A drilled sphere, a glass lens and a die built with CSG",
+    ),
+    (
+        "sdf",
+        "Ray-marched signed distance shapes with blends, twist and repetition",
+    ),
+    ("terrain", "Perlin turbulence heightfield under two spheres"),
+    ("forest", "1,024 instanced trees sharing two meshes"),
+    ("final", "Final scene from Ray Tracing: The Next Week"),
+];
+
+pub fn builtin_scene(name: &str, pool: Option<&ThreadPool>) -> Result<Scene, SceneError> {
+    let mut camera = CameraSettings::default();
+    let mut render = RenderSettings::default();
+    render.background = Color::new(0.7, 0.8, 1.0);
+    let builder = BvhBuilder::new(pool);
+
+    let objects = match name {
+        "random" => {
+            camera.aperture = 0.1;
+            random_scene()
+        }
+        "two_spheres" => two_shpheres(),
+        "two_perlin_spheres" => two_perlin_shpheres(),
+        "earth" => hittable_list_earth(),
+        "simple_light" => {
+            camera.look_from = Point3::new(26.0, 3.0, 6.0);
+            camera.look_at = Point3::new(0.0, 2.0, 0.0);
+            render.background = Color::new(0.0, 0.0, 0.0);
+            render.samples_per_pixel = 400;
+            render.display.tone_mapper = ToneMapper::Aces;
+            hittable_list_simple_light()
+        }
+        "cornell_box" | "cornell_box_smoke" => {
+            render.aspect_ratio = 1.0;
+            render.image_width = 600;
+            render.samples_per_pixel = 200;
+            render.background = Color::new(0.0, 0.0, 0.0);
+            camera.look_from = Point3::new(278.0, 278.0, -800.0);
+            camera.look_at = Point3::new(278.0, 278.0, 0.0);
+            camera.vfov = 40.0;
+            if name == "cornell_box" {
+                cornell_box()
+            } else {
+                cornell_box_smoke()
+            }
+        }
+        "triangles" => {
+            camera.look_from = Point3::new(13.0, 4.0, 5.0);
+            camera.look_at = Point3::new(0.0, 1.0, 0.0);
+            camera.vfov = 25.0;
+            hittable_list_triangles(&builder)
+        }
+        "obj_model" => {
+            camera.look_from = Point3::new(0.0, 2.5, 8.0);
+            camera.look_at = Point3::new(0.0, 0.8, 0.0);
+            camera.vfov = 35.0;
+            render.background = Color::new(0.05, 0.05, 0.08);
+            hittable_list_obj_model(&builder)?
+        }
+        "scans" => {
+            camera.look_from = Point3::new(0.0, 2.5, 7.0);
+            camera.look_at = Point3::new(0.0, 0.8, 0.0);
+            camera.vfov = 35.0;
+            hittable_list_scans(&builder)?
+        }
+        "gltf" => {
+            let imported = load_gltf("assets/models/gltf_demo.gltf", &builder)?;
+            if let Some(gltf_camera) = imported.cameras.first() {
+                camera = gltf_camera.settings;
+            }
+            imported.objects
+        }
+        "shading_normals" => {
+            camera.look_from = Point3::new(0.0, 2.5, 7.0);
+            camera.look_at = Point3::new(0.0, 0.8, 0.0);
+            camera.vfov = 35.0;
+            hittable_list_shading_normals(&builder)?
+        }
+        "quads" => {
+            camera.look_from = Point3::new(0.0, 2.5, 9.0);
+            camera.look_at = Point3::new(0.0, 1.0, 0.0);
+            camera.vfov = 40.0;
+            render.background = Color::new(0.2, 0.25, 0.3);
+            hittable_list_quads()
+        }
+        "shapes" => {
+            camera.look_from = Point3::new(0.0, 3.0, 9.0);
+            camera.look_at = Point3::new(0.0, 0.8, 0.8);
+            camera.vfov = 40.0;
+            render.background = Color::new(0.2, 0.25, 0.3);
+            hittable_list_shapes()
+        }
+        "csg" => {
+            camera.look_from = Point3::new(0.0, 3.0, 8.0);
+            camera.look_at = Point3::new(0.0, 0.9, 0.0);
+            camera.vfov = 40.0;
+            render.background = Color::new(0.2, 0.25, 0.3);
+            hittable_list_csg()
+        }
+        "sdf" => {
+            camera.look_from = Point3::new(0.0, 3.0, 9.0);
+            camera.look_at = Point3::new(0.0, 0.9, 0.5);
+            camera.vfov = 40.0;
+            render.background = Color::new(0.2, 0.25, 0.3);
+            hittable_list_sdf()
+        }
+        "terrain" => {
+            camera.look_from = Point3::new(0.0, 30.0, 70.0);
+            camera.look_at = Point3::new(0.0, 5.0, 0.0);
+            camera.vfov = 40.0;
+            hittable_list_terrain()
+        }
+        "forest" => {
+            camera.look_from = Point3::new(0.0, 9.0, 42.0);
+            camera.look_at = Point3::new(0.0, 0.0, 0.0);
+            camera.vfov = 35.0;
+            hittable_list_forest(&builder)
+        }
+        "final" => {
+            render.image_width = 1920;
+            render.samples_per_pixel = 10000;
+            render.background = Color::new(0.0, 0.0, 0.0);
+            camera.look_from = Point3::new(478.0, 278.0, -600.0);
+            camera.look_at = Point3::new(278.0, 278.0, 0.0);
+            camera.vfov = 40.0;
+            hittalbe_list_final_scene(&builder)
+        }
+        _ => return Err(SceneError::UnknownScene(name.to_string())),
+    };
+
+    let (bvh, bvh_stats) = build_world(&objects, 0.0, 1.0, pool);
+
+    Ok(Scene {
+        world: bvh.clone(),
+        bvh_stats,
+        nested_bvh_stats: builder.stats(),
+        camera,
+        render,
+        bvh,
+        time0: 0.0,
+        time1: 1.0,
+    })
+}
+
+fn build_world(
+    objects: &HittableList,
+    time0: f32,
+    time1: f32,
+    pool: Option<&ThreadPool>,
+) -> (Arc<BvhNode>, Option<BvhStats>) {
+    let (bvh, stats) = match pool {
+        Some(pool) => BvhNode::with_thread_pool(&objects.objects, time0, time1, pool),
+        None => BvhNode::with_stats(&objects.objects, time0, time1),
+    };
+    (Arc::new(bvh), (stats.primitives > 0).then_some(stats))
+}
+
+// glTF files carry no background, so they get the sky of the built-in
+// scenes. Without a camera in the file the view frames the whole model.
+pub fn gltf_scene(path: &str, pool: Option<&ThreadPool>) -> Result<Scene, SceneError> {
+    let builder = BvhBuilder::new(pool);
+    let imported = load_gltf(path, &builder)?;
+    let mut render = RenderSettings::default();
+    render.background = Color::new(0.7, 0.8, 1.0);
+
+    let (bvh, bvh_stats) = build_world(&imported.objects, 0.0, 1.0, pool);
+    let camera = match imported.cameras.first() {
+        Some(gltf_camera) => {
+            if let Some(aspect_ratio) = gltf_camera.aspect_ratio {
+                render.aspect_ratio = aspect_ratio;
+            }
+            gltf_camera.settings
+        }
+        None => {
+            let mut camera = CameraSettings::default();
+            camera.vfov = 40.0;
+            if let Some(world_box) = imported.bounds {
+                let center = 0.5 * (world_box.min() + world_box.max());
+                let radius = 0.5 * (world_box.max() - world_box.min()).length();
+                let distance = radius / (0.5 * camera.vfov).to_radians().sin();
+                camera.look_at = center;
+                camera.look_from = center + distance * Vec3::unit_vector(&Vec3::new(0.0, 0.4, 1.0));
+                camera.focus_dist = distance;
+            }
+            camera
+        }
+    };
+
+    Ok(Scene {
+        world: bvh.clone(),
+        bvh_stats,
+        nested_bvh_stats: builder.stats(),
+        camera,
+        render,
+        bvh,
+        time0: 0.0,
+        time1: 1.0,
+    })
+}
+
+// Scene files are TOML, except for .gltf and .glb models, which are
+// rendered as they are.
+pub fn load_scene(path: &str, pool: Option<&ThreadPool>) -> Result<Scene, SceneError> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    if matches!(extension.as_deref(), Some("gltf" | "glb")) {
+        return gltf_scene(path, pool);
+    }
+
+    let path = Path::new(path);
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse_scene(&source, path, pool)
+}
+
+pub fn parse_scene(
+    source: &str,
+    path: &Path,
+    pool: Option<&ThreadPool>,
+) -> Result<Scene, SceneError> {
+    let mut parser = SceneParser {
+        source,
+        path,
+        dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        texture_tables: HashMap::new(),
+        textures: HashMap::new(),
+        textures_in_progress: HashSet::new(),
+        materials: HashMap::new(),
+        geometries: HashMap::new(),
+        bvh_builder: BvhBuilder::new(pool),
+        time0: 0.0,
+        time1: 1.0,
+    };
+
+    let document = DeTable::parse(source).map_err(|err| {
+        parser.error(
+            err.span().unwrap_or(0..0),
+            err.message().trim_end().to_string(),
+        )
+    })?;
+    let root = Table {
+        table: document.get_ref(),
+        span: document.span(),
+    };
+    parser.check_keys(
+        &root,
+        &[
+            "camera",
+            "render",
+            "textures",
+            "materials",
+            "geometries",
+            "objects",
+        ],
+    )?;
+
+    let camera = match root.get("camera") {
+        Some(value) => parser.camera(&parser.table(value)?)?,
+        None => CameraSettings::default(),
+    };
+    parser.time0 = camera.time0;
+    parser.time1 = camera.time1;
+    let render = match root.get("render") {
+        Some(value) => parser.render(&parser.table(value)?)?,
+        None => RenderSettings::default(),
+    };
+
+    if let Some(value) = root.get("textures") {
+        let textures = parser.table(value)?;
+        for (name, value) in textures.table.iter() {
+            parser
+                .texture_tables
+                .insert(name.get_ref().to_string(), value);
+        }
+        for (name, _) in textures.table.iter() {
+            parser.named_texture(name.get_ref(), name.span())?;
+        }
+    }
+
+    if let Some(value) = root.get("materials") {
+        let materials = parser.table(value)?;
+        for (name, value) in materials.table.iter() {
+            let material = parser.material(&parser.table(value)?)?;
+            parser
+                .materials
+                .insert(name.get_ref().to_string(), material);
+        }
+    }
+
+    if let Some(value) = root.get("geometries") {
+        let geometries = parser.table(value)?;
+        for (name, value) in geometries.table.iter() {
+            let table = parser.table(value)?;
+            parser.check_keys(&table, &["objects"])?;
+            let value = parser.field(&table, "objects")?;
+            let objects = parser.object_list(value)?;
+            if objects.objects.is_empty() {
+                return Err(parser.error(value.span(), "empty geometry".to_string()));
+            }
+            parser.geometries.insert(
+                name.get_ref().to_string(),
+                shared_geometry(&objects, camera.time0, camera.time1, &parser.bvh_builder),
+            );
+        }
+    }
+
+    let objects = match root.get("objects") {
+        Some(value) => parser.object_list(value)?,
+        None => HittableList::new(),
+    };
+
+    let (bvh, bvh_stats) = build_world(&objects, camera.time0, camera.time1, pool);
+
+    Ok(Scene {
+        world: bvh.clone(),
+        bvh_stats,
+        nested_bvh_stats: parser.bvh_builder.stats(),
+        camera,
+        render,
+        bvh,
+        time0: camera.time0,
+        time1: camera.time1,
+    })
+}
+
+type Value<'i> = Spanned<DeValue<'i>>;
+
+const TRANSFORM_KEYS: [&str; 8] = [
+    "matrix",
+    "scale",
+    "rotate_x",
+    "rotate_y",
+    "rotate_z",
+    "rotate",
+    "look_at",
+    "translate",
+];
+
+struct Table<'a, 'i> {
+    table: &'a DeTable<'i>,
+    span: Range<usize>,
+}
+
+impl<'a, 'i> Table<'a, 'i> {
+    fn get(&self, key: &str) -> Option<&'a Value<'i>> {
+        self.table.get(key)
+    }
+}
+
+struct SceneParser<'a, 'i> {
+    source: &'i str,
+    path: &'a Path,
+    dir: PathBuf,
+    texture_tables: HashMap<String, &'a Value<'i>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    textures_in_progress: HashSet<String>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    geometries: HashMap<String, Arc<dyn Hittable>>,
+    bvh_builder: BvhBuilder<'a>,
+    // The shutter interval, which BVHs built inside the scene have to cover.
+    time0: f32,
+    time1: f32,
+}
+
+impl<'a, 'i> SceneParser<'a, 'i> {
+    fn error(&self, span: Range<usize>, message: String) -> SceneError {
+        let start = span.start.min(self.source.len());
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let source_line = self.source[line_start..line_end].trim_end().to_string();
+        let column = self.source[line_start..start].chars().count() + 1;
+        let width = span
+            .end
+            .min(line_end)
+            .saturating_sub(start)
+            .clamp(1, source_line.len().saturating_sub(column - 1).max(1));
+
+        SceneError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.source[..start].matches('\n').count() + 1,
+            column,
+            message,
+            source_line,
+            width,
+        }
+    }
+
+    fn check_keys(&self, table: &Table, allowed: &[&str]) -> Result<(), SceneError> {
+        for key in table.table.keys() {
+            if !allowed.contains(&key.get_ref().as_ref()) {
+                return Err(self.error(
+                    key.span(),
+                    format!(
+                        "unknown field `{}`, expected one of: {}",
+                        key.get_ref(),
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn field<'v>(&self, table: &Table<'v, 'i>, key: &str) -> Result<&'v Value<'i>, SceneError> {
+        table
+            .get(key)
+            .ok_or_else(|| self.error(table.span.clone(), format!("missing field `{}`", key)))
+    }
+
+    fn table<'v>(&self, value: &'v Value<'i>) -> Result<Table<'v, 'i>, SceneError> {
+        match value.get_ref() {
+            DeValue::Table(table) => Ok(Table {
+                table,
+                span: value.span(),
+            }),
+            _ => Err(self.error(value.span(), "expected a table".to_string())),
+        }
+    }
+
+    fn array<'v>(&self, value: &'v Value<'i>) -> Result<&'v [Value<'i>], SceneError> {
+        match value.get_ref() {
+            DeValue::Array(array) => Ok(array),
+            _ => Err(self.error(value.span(), "expected an array".to_string())),
+        }
+    }
+
+    fn string<'v>(&self, value: &'v Value<'i>) -> Result<&'v str, SceneError> {
+        match value.get_ref() {
+            DeValue::String(s) => Ok(s),
+            _ => Err(self.error(value.span(), "expected a string".to_string())),
+        }
+    }
+
+    fn boolean(&self, value: &Value) -> Result<bool, SceneError> {
+        match value.get_ref() {
+            DeValue::Boolean(b) => Ok(*b),
+            _ => Err(self.error(value.span(), "expected a boolean".to_string())),
+        }
+    }
+
+    fn number(&self, value: &Value) -> Result<f32, SceneError> {
+        let parsed = match value.get_ref() {
+            DeValue::Integer(i) if i.radix() == 10 => i.as_str().replace('_', "").parse().ok(),
+            DeValue::Float(f) => f.as_str().replace('_', "").parse().ok(),
+            _ => None,
+        };
+        parsed.ok_or_else(|| self.error(value.span(), "expected a number".to_string()))
+    }
+
+    fn integer(&self, value: &Value) -> Result<usize, SceneError> {
+        let parsed = match value.get_ref() {
+            DeValue::Integer(i) => usize::from_str_radix(&i.as_str().replace('_', ""), i.radix())
+                .ok()
+                .filter(|n| *n > 0),
+            _ => None,
+        };
+        parsed.ok_or_else(|| self.error(value.span(), "expected a positive integer".to_string()))
+    }
+
+    fn vec3(&self, value: &Value) -> Result<Vec3, SceneError> {
+        let array = self.array(value)?;
+        if array.len() != 3 {
+            return Err(self.error(
+                value.span(),
+                format!("expected 3 components, found {}", array.len()),
+            ));
+        }
+        Ok(Vec3::new(
+            self.number(&array[0])?,
+            self.number(&array[1])?,
+            self.number(&array[2])?,
+        ))
+    }
+
+    fn number_field(&self, table: &Table, key: &str) -> Result<f32, SceneError> {
+        self.number(self.field(table, key)?)
+    }
+
+    fn positive_field(&self, table: &Table, key: &str) -> Result<f32, SceneError> {
+        let value = self.field(table, key)?;
+        let number = self.number(value)?;
+        if number <= 0.0 {
+            return Err(self.error(
+                value.span(),
+                format!("{} must be positive", key.replace('_', " ")),
+            ));
+        }
+        Ok(number)
+    }
+
+    fn vec3_field(&self, table: &Table, key: &str) -> Result<Vec3, SceneError> {
+        self.vec3(self.field(table, key)?)
+    }
+
+    fn normal_field(&self, table: &Table, key: &str) -> Result<Vec3, SceneError> {
+        let value = self.field(table, key)?;
+        let normal = self.vec3(value)?;
+        if normal.near_zero() {
+            return Err(self.error(value.span(), format!("`{}` must be non-zero", key)));
+        }
+        Ok(normal)
+    }
+
+    fn object_type<'v>(&self, table: &Table<'v, 'i>) -> Result<&'v str, SceneError> {
+        self.string(self.field(table, "type")?)
+    }
+
+    fn unknown_type(&self, table: &Table, kind: &str, expected: &[&str]) -> SceneError {
+        let value = table.get("type").unwrap();
+        self.error(
+            value.span(),
+            format!(
+                "unknown {} type `{}`, expected one of: {}",
+                kind,
+                self.string(value).unwrap_or(""),
+                expected.join(", ")
+            ),
+        )
+    }
+
+    fn resolve_path(&self, value: &Value) -> Result<PathBuf, SceneError> {
+        Ok(self.dir.join(self.string(value)?))
+    }
+
+    fn camera(&self, table: &Table) -> Result<CameraSettings, SceneError> {
+        self.check_keys(
+            table,
+            &[
+                "look_from",
+                "look_at",
+                "vup",
+                "vfov",
+                "aperture",
+                "focus_dist",
+                "time0",
+                "time1",
+            ],
+        )?;
+
+        let mut camera = CameraSettings::default();
+        camera.look_from = self.vec3_field(table, "look_from")?;
+        camera.look_at = self.vec3_field(table, "look_at")?;
+        if let Some(value) = table.get("vup") {
+            camera.vup = self.vec3(value)?;
+        }
+        if let Some(value) = table.get("vfov") {
+            camera.vfov = self.number(value)?;
+        }
+        if let Some(value) = table.get("aperture") {
+            camera.aperture = self.number(value)?;
+        }
+        if let Some(value) = table.get("focus_dist") {
+            camera.focus_dist = self.number(value)?;
+        }
+        if let Some(value) = table.get("time0") {
+            camera.time0 = self.number(value)?;
+        }
+        if let Some(value) = table.get("time1") {
+            camera.time1 = self.number(value)?;
+        }
+        Ok(camera)
+    }
+
+    fn render(&self, table: &Table) -> Result<RenderSettings, SceneError> {
+        self.check_keys(
+            table,
+            &[
+                "width",
+                "aspect_ratio",
+                "samples_per_pixel",
+                "max_depth",
+                "background",
+                "exposure",
+                "tone_map",
+                "white_point",
+            ],
+        )?;
+
+        let mut render = RenderSettings::default();
+        if let Some(value) = table.get("width") {
+            render.image_width = self.integer(value)?;
+        }
+        if let Some(value) = table.get("aspect_ratio") {
+            render.aspect_ratio = self.number(value)?;
+            if render.aspect_ratio <= 0.0 {
+                return Err(self.error(value.span(), "aspect ratio must be positive".to_string()));
+            }
+        }
+        if let Some(value) = table.get("samples_per_pixel") {
+            render.samples_per_pixel = self.integer(value)? as i32;
+        }
+        if let Some(value) = table.get("max_depth") {
+            render.max_depth = self.integer(value)? as i32;
+        }
+        if let Some(value) = table.get("background") {
+            render.background = self.vec3(value)?;
+        }
+        if let Some(value) = table.get("exposure") {
+            render.display.exposure = self.number(value)?;
+        }
+        if let Some(value) = table.get("tone_map") {
+            let name = self.string(value)?;
+            render.display.tone_mapper = ToneMapper::from_name(name).ok_or_else(|| {
+                self.error(
+                    value.span(),
+                    format!(
+                        "unknown tone mapper `{}`, expected one of: {}",
+                        name,
+                        ToneMapper::NAMES.join(", ")
+                    ),
+                )
+            })?;
+        }
+        if let Some(value) = table.get("white_point") {
+            let white_point = self.number(value)?;
+            if white_point <= 0.0 {
+                return Err(self.error(value.span(), "white point must be positive".to_string()));
+            }
+            render.display.white_point = Some(white_point);
+        }
+        Ok(render)
+    }
+
+    fn named_texture(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(Arc::clone(texture));
+        }
+        let value = match self.texture_tables.get(name) {
+            Some(value) => *value,
+            None => return Err(self.error(span, format!("unknown texture `{}`", name))),
+        };
+        if !self.textures_in_progress.insert(name.to_string()) {
+            return Err(self.error(span, format!("texture `{}` references itself", name)));
+        }
+
+        let texture = self.texture(&self.table(value)?)?;
+        self.textures_in_progress.remove(name);
+        self.textures.insert(name.to_string(), Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    fn texture_ref(&mut self, value: &Value) -> Result<Arc<dyn Texture>, SceneError> {
+        match value.get_ref() {
+            DeValue::String(name) => self.named_texture(name, value.span()),
+            DeValue::Table(_) => self.texture(&self.table(value)?),
+            _ => Ok(Arc::new(SolidColor::new(self.vec3(value)?))),
+        }
+    }
+
+    fn texture(&mut self, table: &Table) -> Result<Arc<dyn Texture>, SceneError> {
+        const TYPES: [&str; 5] = ["solid", "checker", "noise", "image", "vertex_color"];
+
+        Ok(match self.object_type(table)? {
+            "solid" => {
+                self.check_keys(table, &["type", "color"])?;
+                Arc::new(SolidColor::new(self.vec3_field(table, "color")?))
+            }
+            "checker" => {
+                self.check_keys(table, &["type", "odd", "even"])?;
+                let odd = self.texture_ref(self.field(table, "odd")?)?;
+                let even = self.texture_ref(self.field(table, "even")?)?;
+                Arc::new(CheckerTexture::new(odd, even))
+            }
+            "noise" => {
+                self.check_keys(table, &["type", "scale"])?;
+                Arc::new(NoiseTexture::new(self.number_field(table, "scale")?))
+            }
+            "image" => {
+                self.check_keys(table, &["type", "path", "srgb"])?;
+                let srgb = match table.get("srgb") {
+                    Some(value) => self.boolean(value)?,
+                    None => true,
+                };
+                let value = self.field(table, "path")?;
+                let path = self.resolve_path(value)?;
+                let texture = ImageTexture::load(&path.to_string_lossy(), srgb).map_err(|err| {
+                    self.error(value.span(), format!("{}: {}", path.display(), err))
+                })?;
+                Arc::new(texture)
+            }
+            "vertex_color" => {
+                self.check_keys(table, &["type", "fallback"])?;
+                match table.get("fallback") {
+                    Some(value) => Arc::new(VertexColorTexture::new(self.texture_ref(value)?)),
+                    None => Arc::new(VertexColorTexture::with_color(Color::new(0.73, 0.73, 0.73))),
+                }
+            }
+            _ => return Err(self.unknown_type(table, "texture", &TYPES)),
+        })
+    }
+
+    fn material_ref(&mut self, value: &Value) -> Result<Arc<dyn Material>, SceneError> {
+        match value.get_ref() {
+            DeValue::String(name) => match self.materials.get(name.as_ref()) {
+                Some(material) => Ok(Arc::clone(material)),
+                None => Err(self.error(value.span(), format!("unknown material `{}`", name))),
+            },
+            _ => self.material(&self.table(value)?),
+        }
+    }
+
+    fn material(&mut self, table: &Table) -> Result<Arc<dyn Material>, SceneError> {
+        const TYPES: [&str; 5] = [
+            "lambertian",
+            "metal",
+            "dielectric",
+            "diffuse_light",
+            "normal_mapped",
+        ];
+
+        Ok(match self.object_type(table)? {
+            "lambertian" => {
+                self.check_keys(table, &["type", "albedo"])?;
+                Arc::new(Lambertian::new(
+                    self.texture_ref(self.field(table, "albedo")?)?,
+                ))
+            }
+            "metal" => {
+                self.check_keys(table, &["type", "albedo", "fuzz"])?;
+                let fuzz = match table.get("fuzz") {
+                    Some(value) => self.number(value)?,
+                    None => 0.0,
+                };
+                Arc::new(Metal::new(&self.vec3_field(table, "albedo")?, fuzz))
+            }
+            "dielectric" => {
+                self.check_keys(table, &["type", "ir"])?;
+                Arc::new(Dielectric::new(self.number_field(table, "ir")?))
+            }
+            "diffuse_light" => {
+                self.check_keys(table, &["type", "emit"])?;
+                Arc::new(DiffuseLight::with_texture(
+                    self.texture_ref(self.field(table, "emit")?)?,
+                ))
+            }
+            "normal_mapped" => {
+                self.check_keys(table, &["type", "material", "normal_map", "scale"])?;
+                let scale = match table.get("scale") {
+                    Some(value) => self.number(value)?,
+                    None => 1.0,
+                };
+                Arc::new(NormalMapped::with_scale(
+                    self.material_ref(self.field(table, "material")?)?,
+                    self.texture_ref(self.field(table, "normal_map")?)?,
+                    scale,
+                ))
+            }
+            _ => return Err(self.unknown_type(table, "material", &TYPES)),
+        })
+    }
+
+    fn object_list(&mut self, value: &Value) -> Result<HittableList, SceneError> {
+        let mut objects = HittableList::new();
+        for item in self.array(value)? {
+            objects.add(self.object(&self.table(item)?)?);
+        }
+        Ok(objects)
+    }
+
+    fn rect_bounds(&self, table: &Table, axes: [&str; 2]) -> Result<[f32; 5], SceneError> {
+        let a = axes[0];
+        let b = axes[1];
+        self.check_keys(
+            table,
+            &[
+                "type",
+                &format!("{}0", a),
+                &format!("{}1", a),
+                &format!("{}0", b),
+                &format!("{}1", b),
+                "k",
+                "material",
+            ],
+        )?;
+        Ok([
+            self.number_field(table, &format!("{}0", a))?,
+            self.number_field(table, &format!("{}1", a))?,
+            self.number_field(table, &format!("{}0", b))?,
+            self.number_field(table, &format!("{}1", b))?,
+            self.number_field(table, "k")?,
+        ])
+    }
+
+    fn object(&mut self, table: &Table) -> Result<Arc<dyn Hittable>, SceneError> {
+        const TYPES: [&str; 33] = [
+            "sphere",
+            "moving_sphere",
+            "cylinder",
+            "cone",
+            "paraboloid",
+            "hyperboloid",
+            "torus",
+            "sdf",
+            "heightfield",
+            "xy_rect",
+            "xz_rect",
+            "yz_rect",
+            "cube",
+            "quad",
+            "disk",
+            "plane",
+            "triangle",
+            "mesh",
+            "obj",
+            "gltf",
+            "ply",
+            "stl",
+            "translate",
+            "rotate_y",
+            "transform",
+            "animated",
+            "constant_medium",
+            "union",
+            "intersection",
+            "difference",
+            "list",
+            "bvh",
+            "instance",
+        ];
+
+        Ok(match self.object_type(table)? {
+            "sphere" => {
+                self.check_keys(table, &["type", "center", "radius", "material"])?;
+                Arc::new(Sphere::with_center_and_radius(
+                    self.vec3_field(table, "center")?,
+                    self.number_field(table, "radius")?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "moving_sphere" => {
+                self.check_keys(
+                    table,
+                    &[
+                        "type", "center0", "center1", "time0", "time1", "radius", "material",
+                    ],
+                )?;
+                Arc::new(MovingSphere::new(
+                    self.vec3_field(table, "center0")?,
+                    self.vec3_field(table, "center1")?,
+                    self.number_field(table, "time0")?,
+                    self.number_field(table, "time1")?,
+                    self.number_field(table, "radius")?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "cylinder" | "cone" | "paraboloid" | "hyperboloid" => self.quadric(table)?,
+            "torus" => {
+                self.check_keys(
+                    table,
+                    &[
+                        "type",
+                        "center",
+                        "major_radius",
+                        "minor_radius",
+                        "sweep",
+                        "material",
+                    ],
+                )?;
+                let center = self.vec3_field(table, "center")?;
+                let value = self.field(table, "major_radius")?;
+                let major_radius = self.number(value)?;
+                if major_radius < 0.0 {
+                    return Err(self.error(
+                        value.span(),
+                        "major radius must not be negative".to_string(),
+                    ));
+                }
+                Arc::new(Torus::with_sweep(
+                    center,
+                    major_radius,
+                    self.positive_field(table, "minor_radius")?,
+                    self.sweep(table)?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "sdf" => {
+                self.check_keys(table, &["type", "shape", "min", "max", "material"])?;
+                let min = self.vec3_field(table, "min")?;
+                let value = self.field(table, "max")?;
+                let max = self.vec3(value)?;
+                if (0..3).any(|c| max[c] <= min[c]) {
+                    return Err(self.error(
+                        value.span(),
+                        "`max` must be greater than `min` on every axis".to_string(),
+                    ));
+                }
+                Arc::new(SdfObject::new(
+                    self.sdf(&self.table(self.field(table, "shape")?)?)?,
+                    AABB::new(&min, &max),
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "heightfield" => self.heightfield(table)?,
+            "xy_rect" => {
+                let [x0, x1, y0, y1, k] = self.rect_bounds(table, ["x", "y"])?;
+                let mat = self.material_ref(self.field(table, "material")?)?;
+                Arc::new(XYRect::new(x0, x1, y0, y1, k, mat))
+            }
+            "xz_rect" => {
+                let [x0, x1, z0, z1, k] = self.rect_bounds(table, ["x", "z"])?;
+                let mat = self.material_ref(self.field(table, "material")?)?;
+                Arc::new(XZRect::new(x0, x1, z0, z1, k, mat))
+            }
+            "yz_rect" => {
+                let [y0, y1, z0, z1, k] = self.rect_bounds(table, ["y", "z"])?;
+                let mat = self.material_ref(self.field(table, "material")?)?;
+                Arc::new(YZRect::new(y0, y1, z0, z1, k, mat))
+            }
+            "cube" => {
+                self.check_keys(table, &["type", "min", "max", "material"])?;
+                Arc::new(Cube::new(
+                    &self.vec3_field(table, "min")?,
+                    &self.vec3_field(table, "max")?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "quad" => {
+                self.check_keys(table, &["type", "origin", "u", "v", "material"])?;
+                let u = self.vec3_field(table, "u")?;
+                let value = self.field(table, "v")?;
+                let v = self.vec3(value)?;
+                if Vec3::cross(&u, &v).near_zero() {
+                    return Err(
+                        self.error(value.span(), "`u` and `v` must not be parallel".to_string())
+                    );
+                }
+                Arc::new(Quad::new(
+                    self.vec3_field(table, "origin")?,
+                    u,
+                    v,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "disk" => {
+                self.check_keys(table, &["type", "center", "normal", "radius", "material"])?;
+                Arc::new(Disk::new(
+                    self.vec3_field(table, "center")?,
+                    self.normal_field(table, "normal")?,
+                    self.number_field(table, "radius")?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "plane" => {
+                self.check_keys(table, &["type", "point", "normal", "tile_size", "material"])?;
+                let tile_size = match table.get("tile_size") {
+                    Some(value) => {
+                        let tile_size = self.number(value)?;
+                        if tile_size <= 0.0 {
+                            return Err(
+                                self.error(value.span(), "tile size must be positive".to_string())
+                            );
+                        }
+                        tile_size
+                    }
+                    None => 1.0,
+                };
+                Arc::new(Plane::with_tile_size(
+                    self.vec3_field(table, "point")?,
+                    self.normal_field(table, "normal")?,
+                    tile_size,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "triangle" => {
+                self.check_keys(table, &["type", "vertices", "material"])?;
+                let value = self.field(table, "vertices")?;
+                let vertices = self.array(value)?;
+                if vertices.len() != 3 {
+                    return Err(self.error(
+                        value.span(),
+                        format!("expected 3 vertices, found {}", vertices.len()),
+                    ));
+                }
+                Arc::new(Triangle::new(
+                    self.vec3(&vertices[0])?,
+                    self.vec3(&vertices[1])?,
+                    self.vec3(&vertices[2])?,
+                    self.material_ref(self.field(table, "material")?)?,
+                ))
+            }
+            "mesh" => self.mesh(table)?,
+            "obj" => {
+                self.check_keys(table, &["type", "path"])?;
+                let value = self.field(table, "path")?;
+                let path = self.resolve_path(value)?;
+                let objects = load_obj(&path.to_string_lossy(), &self.bvh_builder)
+                    .map_err(|err| self.error(value.span(), err.to_string()))?;
+                Arc::new(
+                    self.bvh_builder
+                        .build(&objects.objects, self.time0, self.time1),
+                )
+            }
+            "gltf" => {
+                self.check_keys(table, &["type", "path"])?;
+                let value = self.field(table, "path")?;
+                let path = self.resolve_path(value)?;
+                let imported = load_gltf(&path.to_string_lossy(), &self.bvh_builder)
+                    .map_err(|err| self.error(value.span(), err.to_string()))?;
+                Arc::new(
+                    self.bvh_builder
+                        .build(&imported.objects.objects, self.time0, self.time1),
+                )
+            }
+            "ply" => {
+                self.check_keys(table, &["type", "path", "material"])?;
+                let value = self.field(table, "path")?;
+                let path = self.resolve_path(value)?;
+                // Scans usually carry their color in the vertices.
+                let mat: Arc<dyn Material> = match table.get("material") {
+                    Some(value) => self.material_ref(value)?,
+                    None => Arc::new(Lambertian::new(Arc::new(VertexColorTexture::with_color(
+                        Color::new(0.73, 0.73, 0.73),
+                    )))),
+                };
+                Arc::new(
+                    load_ply(&path.to_string_lossy(), mat, &self.bvh_builder)
+                        .map_err(|err| self.error(value.span(), err.to_string()))?,
+                )
+            }
+            "stl" => {
+                self.check_keys(table, &["type", "path", "material"])?;
+                let value = self.field(table, "path")?;
+                let path = self.resolve_path(value)?;
+                let mat: Arc<dyn Material> = match table.get("material") {
+                    Some(value) => self.material_ref(value)?,
+                    None => Arc::new(Lambertian::with_color(&Color::new(0.73, 0.73, 0.73))),
+                };
+                Arc::new(
+                    load_stl(&path.to_string_lossy(), mat, &self.bvh_builder)
+                        .map_err(|err| self.error(value.span(), err.to_string()))?,
+                )
+            }
+            "translate" => {
+                self.check_keys(table, &["type", "offset", "object"])?;
+                let object = self.object(&self.table(self.field(table, "object")?)?)?;
+                Arc::new(Translate::new(object, &self.vec3_field(table, "offset")?))
+            }
+            "rotate_y" => {
+                self.check_keys(table, &["type", "angle", "object"])?;
+                let object = self.object(&self.table(self.field(table, "object")?)?)?;
+                Arc::new(RotateY::new(object, self.number_field(table, "angle")?))
+            }
+            "constant_medium" => {
+                self.check_keys(table, &["type", "density", "albedo", "boundary"])?;
+                let boundary = self.object(&self.table(self.field(table, "boundary")?)?)?;
+                let albedo = self.texture_ref(self.field(table, "albedo")?)?;
+                Arc::new(ConstantMedium::with_texture(
+                    boundary,
+                    self.number_field(table, "density")?,
+                    albedo,
+                ))
+            }
+            "union" | "intersection" | "difference" => {
+                self.check_keys(table, &["type", "left", "right"])?;
+                let operation = match self.object_type(table)? {
+                    "union" => CsgOperation::Union,
+                    "intersection" => CsgOperation::Intersection,
+                    _ => CsgOperation::Difference,
+                };
+                let left = self.object(&self.table(self.field(table, "left")?)?)?;
+                let right = self.object(&self.table(self.field(table, "right")?)?)?;
+                Arc::new(Csg::new(operation, left, right))
+            }
+            "list" | "bvh" => {
+                self.check_keys(table, &["type", "objects"])?;
+                let value = self.field(table, "objects")?;
+                let objects = self.object_list(value)?;
+                if objects.objects.is_empty() {
+                    return Err(self.error(value.span(), "empty object group".to_string()));
+                }
+                if self.object_type(table)? == "bvh" {
+                    Arc::new(
+                        self.bvh_builder
+                            .build(&objects.objects, self.time0, self.time1),
+                    )
+                } else {
+                    Arc::new(objects)
+                }
+            }
+            "transform" => {
+                self.check_keys(table, &[&["type", "object"][..], &TRANSFORM_KEYS].concat())?;
+                let object = self.object(&self.table(self.field(table, "object")?)?)?;
+                Arc::new(Transformed::new(object, self.transform(table)?))
+            }
+            "animated" => {
+                self.check_keys(table, &["type", "keyframes", "object"])?;
+                let object = self.object(&self.table(self.field(table, "object")?)?)?;
+                let keyframes = self.keyframes(self.field(table, "keyframes")?)?;
+                Arc::new(Animated::new(object, keyframes))
+            }
+            "instance" => {
+                self.check_keys(
+                    table,
+                    &[&["type", "geometry", "material"][..], &TRANSFORM_KEYS].concat(),
+                )?;
+                let value = self.field(table, "geometry")?;
+                let name = self.string(value)?;
+                let geometry = match self.geometries.get(name) {
+                    Some(geometry) => Arc::clone(geometry),
+                    None => {
+                        return Err(self.error(value.span(), format!("unknown geometry `{}`", name)))
+                    }
+                };
+                let transform = self.transform(table)?;
+                match table.get("material") {
+                    Some(value) => Arc::new(Instance::with_material(
+                        geometry,
+                        transform,
+                        self.material_ref(value)?,
+                    )),
+                    None => Arc::new(Instance::new(geometry, transform)),
+                }
+            }
+            _ => return Err(self.unknown_type(table, "object", &TYPES)),
+        })
+    }
+
+    fn sdf(&self, table: &Table) -> Result<Arc<dyn Sdf>, SceneError> {
+        const TYPES: [&str; 9] = [
+            "sphere",
+            "box",
+            "rounded_box",
+            "torus",
+            "capsule",
+            "smooth_union",
+            "smooth_subtraction",
+            "repeat",
+            "twist",
+        ];
+
+        Ok(match self.object_type(table)? {
+            "sphere" => {
+                self.check_keys(table, &["type", "center", "radius"])?;
+                Arc::new(SdfSphere::new(
+                    self.vec3_field(table, "center")?,
+                    self.number_field(table, "radius")?,
+                ))
+            }
+            "box" => {
+                self.check_keys(table, &["type", "center", "half_size"])?;
+                Arc::new(SdfBox::new(
+                    self.vec3_field(table, "center")?,
+                    self.vec3_field(table, "half_size")?,
+                ))
+            }
+            "rounded_box" => {
+                self.check_keys(table, &["type", "center", "half_size", "radius"])?;
+                Arc::new(SdfBox::rounded(
+                    self.vec3_field(table, "center")?,
+                    self.vec3_field(table, "half_size")?,
+                    self.number_field(table, "radius")?,
+                ))
+            }
+            "torus" => {
+                self.check_keys(table, &["type", "center", "major_radius", "minor_radius"])?;
+                Arc::new(SdfTorus::new(
+                    self.vec3_field(table, "center")?,
+                    self.number_field(table, "major_radius")?,
+                    self.number_field(table, "minor_radius")?,
+                ))
+            }
+            "capsule" => {
+                self.check_keys(table, &["type", "a", "b", "radius"])?;
+                Arc::new(SdfCapsule::new(
+                    self.vec3_field(table, "a")?,
+                    self.vec3_field(table, "b")?,
+                    self.number_field(table, "radius")?,
+                ))
+            }
+            "smooth_union" | "smooth_subtraction" => {
+                self.check_keys(table, &["type", "left", "right", "smoothness"])?;
+                let left = self.sdf(&self.table(self.field(table, "left")?)?)?;
+                let right = self.sdf(&self.table(self.field(table, "right")?)?)?;
+                let k = match table.get("smoothness") {
+                    Some(value) => self.number(value)?,
+                    None => 0.0,
+                };
+                if self.object_type(table)? == "smooth_union" {
+                    Arc::new(SmoothUnion::new(left, right, k))
+                } else {
+                    Arc::new(SmoothSubtraction::new(left, right, k))
+                }
+            }
+            "repeat" => {
+                self.check_keys(table, &["type", "period", "shape"])?;
+                let value = self.field(table, "period")?;
+                let period = self.vec3(value)?;
+                if (0..3).any(|c| period[c] < 0.0) {
+                    return Err(self.error(value.span(), "period must not be negative".to_string()));
+                }
+                Arc::new(Repeat::new(
+                    self.sdf(&self.table(self.field(table, "shape")?)?)?,
+                    period,
+                ))
+            }
+            "twist" => {
+                self.check_keys(table, &["type", "rate", "shape"])?;
+                Arc::new(Twist::new(
+                    self.sdf(&self.table(self.field(table, "shape")?)?)?,
+                    self.number_field(table, "rate")?,
+                ))
+            }
+            _ => return Err(self.unknown_type(table, "shape", &TYPES)),
+        })
+    }
+
+    fn heightfield(&mut self, table: &Table) -> Result<Arc<dyn Hittable>, SceneError> {
+        self.check_keys(
+            table,
+            &["type", "image", "noise", "origin", "size", "material"],
+        )?;
+        let origin = self.vec3_field(table, "origin")?;
+        let value = self.field(table, "size")?;
+        let size = self.vec3(value)?;
+        if size.x() <= 0.0 || size.z() <= 0.0 {
+            return Err(self.error(
+                value.span(),
+                "size must be positive along x and z".to_string(),
+            ));
+        }
+        let mat = self.material_ref(self.field(table, "material")?)?;
+
+        Ok(match (table.get("image"), table.get("noise")) {
+            (Some(value), None) => {
+                let path = self.resolve_path(value)?;
+                let heightfield =
+                    Heightfield::from_image(&path.to_string_lossy(), origin, size, mat).map_err(
+                        |err| self.error(value.span(), format!("{}: {}", path.display(), err)),
+                    )?;
+                Arc::new(heightfield)
+            }
+            (None, Some(value)) => {
+                let noise = self.table(value)?;
+                self.check_keys(&noise, &["scale", "depth", "resolution"])?;
+                let depth = match noise.get("depth") {
+                    Some(value) => self.integer(value)? as i32,
+                    None => 7,
+                };
+                let value = self.field(&noise, "resolution")?;
+                let resolution = self.array(value)?;
+                if resolution.len() != 2 {
+                    return Err(self.error(
+                        value.span(),
+                        format!("expected 2 components, found {}", resolution.len()),
+                    ));
+                }
+                let samples_x = self.integer(&resolution[0])?;
+                let samples_z = self.integer(&resolution[1])?;
+                if samples_x < 2 || samples_z < 2 {
+                    return Err(self.error(
+                        value.span(),
+                        "resolution must be at least 2 samples per side".to_string(),
+                    ));
+                }
+                Arc::new(Heightfield::from_noise(
+                    &Perlin::new(),
+                    self.number_field(&noise, "scale")?,
+                    depth,
+                    samples_x,
+                    samples_z,
+                    origin,
+                    size,
+                    mat,
+                ))
+            }
+            _ => {
+                return Err(self.error(
+                    table.span.clone(),
+                    "heightfield needs exactly one of `image` or `noise`".to_string(),
+                ))
+            }
+        })
+    }
+
+    fn sweep(&self, table: &Table) -> Result<f32, SceneError> {
+        match table.get("sweep") {
+            Some(value) => {
+                let sweep = self.number(value)?;
+                if sweep <= 0.0 || sweep > 360.0 {
+                    return Err(self.error(
+                        value.span(),
+                        "sweep must be in (0, 360] degrees".to_string(),
+                    ));
+                }
+                Ok(sweep)
+            }
+            None => Ok(360.0),
+        }
+    }
+
+    fn quadric(&mut self, table: &Table) -> Result<Arc<dyn Hittable>, SceneError> {
+        let kind = self.object_type(table)?;
+        let keys = [
+            "type", "base", "radius", "height", "sweep", "capped", "material",
+        ];
+        if kind == "hyperboloid" {
+            self.check_keys(table, &[&keys[..], &["waist_radius"]].concat())?;
+        } else {
+            self.check_keys(table, &keys)?;
+        }
+
+        let base = self.vec3_field(table, "base")?;
+        let radius = self.positive_field(table, "radius")?;
+        let height = self.positive_field(table, "height")?;
+        let waist_radius = if kind == "hyperboloid" {
+            let value = self.field(table, "waist_radius")?;
+            let waist_radius = self.number(value)?;
+            if waist_radius > radius {
+                return Err(self.error(
+                    value.span(),
+                    format!("waist radius must not exceed the radius ({})", radius),
+                ));
+            }
+            waist_radius
+        } else {
+            0.0
+        };
+        let sweep = self.sweep(table)?;
+        let capped = match table.get("capped") {
+            Some(value) => self.boolean(value)?,
+            None => false,
+        };
+        let mat = self.material_ref(self.field(table, "material")?)?;
+
+        Ok(match kind {
+            "cylinder" => Arc::new(Cylinder::with_options(
+                base, radius, height, sweep, capped, mat,
+            )),
+            "cone" => Arc::new(Cone::with_options(base, radius, height, sweep, capped, mat)),
+            "paraboloid" => Arc::new(Paraboloid::with_options(
+                base, radius, height, sweep, capped, mat,
+            )),
+            _ => Arc::new(Hyperboloid::with_options(
+                base,
+                radius,
+                waist_radius,
+                height,
+                sweep,
+                capped,
+                mat,
+            )),
+        })
+    }
+
+    fn keyframes(&self, value: &Value) -> Result<Vec<Keyframe>, SceneError> {
+        let mut keyframes: Vec<Keyframe> = vec![];
+        for item in self.array(value)? {
+            let table = self.table(item)?;
+            self.check_keys(&table, &["time", "translate", "rotate", "scale"])?;
+
+            let time_value = self.field(&table, "time")?;
+            let time = self.number(time_value)?;
+            if let Some(previous) = keyframes.last() {
+                if time <= previous.time {
+                    return Err(self.error(
+                        time_value.span(),
+                        format!(
+                            "keyframe times must increase, previous was {}",
+                            previous.time
+                        ),
+                    ));
+                }
+            }
+
+            let translation = match table.get("translate") {
+                Some(value) => self.vec3(value)?,
+                None => Vec3::default(),
+            };
+            let rotation = match table.get("rotate") {
+                Some(value) => self.rotation(value)?,
+                None => Quat::identity(),
+            };
+            let scale = match table.get("scale") {
+                Some(value) => self.scale(value)?,
+                None => Vec3::new(1.0, 1.0, 1.0),
+            };
+            keyframes.push(Keyframe::new(time, translation, rotation, scale));
+        }
+
+        if keyframes.is_empty() {
+            return Err(self.error(value.span(), "expected at least one keyframe".to_string()));
+        }
+        Ok(keyframes)
+    }
+
+    // `matrix` is a row-major 4x4 matrix. Otherwise each step is optional:
+    // scale, rotate about x, y and z, `rotate`, `look_at`, then translate.
+    fn transform(&self, table: &Table) -> Result<Transform, SceneError> {
+        let matrix = match table.get("matrix") {
+            Some(value) => {
+                if let Some(key) = TRANSFORM_KEYS[1..]
+                    .iter()
+                    .find(|key| table.get(key).is_some())
+                {
+                    return Err(self.error(
+                        value.span(),
+                        format!("`matrix` cannot be combined with `{}`", key),
+                    ));
+                }
+                self.matrix(value)?
+            }
+            None => self.transform_steps(table)?,
+        };
+
+        Transform::new(matrix).ok_or_else(|| {
+            let span = table.get("matrix").map_or(table.span.clone(), |v| v.span());
+            self.error(span, "transform is not invertible".to_string())
+        })
+    }
+
+    fn matrix(&self, value: &Value) -> Result<Mat4, SceneError> {
+        let rows = self.array(value)?;
+        if rows.len() != 4 {
+            return Err(self.error(
+                value.span(),
+                format!("expected 4 rows, found {}", rows.len()),
+            ));
+        }
+        let mut matrix = Mat4::identity();
+        for (i, row) in rows.iter().enumerate() {
+            let columns = self.array(row)?;
+            if columns.len() != 4 {
+                return Err(self.error(
+                    row.span(),
+                    format!("expected 4 columns, found {}", columns.len()),
+                ));
+            }
+            for (j, column) in columns.iter().enumerate() {
+                matrix.m[i][j] = self.number(column)?;
+            }
+        }
+        // Normals and bounding boxes are transformed as affine maps only.
+        if matrix.m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return Err(self.error(
+                rows[3].span(),
+                "the last row must be [0, 0, 0, 1]".to_string(),
+            ));
+        }
+        Ok(matrix)
+    }
+
+    fn transform_steps(&self, table: &Table) -> Result<Mat4, SceneError> {
+        let mut transform = Mat4::identity();
+        if let Some(value) = table.get("scale") {
+            transform = Mat4::scaling(&self.scale(value)?);
+        }
+        if let Some(value) = table.get("rotate_x") {
+            transform = Mat4::rotation_x(self.number(value)?) * transform;
+        }
+        if let Some(value) = table.get("rotate_y") {
+            transform = Mat4::rotation_y(self.number(value)?) * transform;
+        }
+        if let Some(value) = table.get("rotate_z") {
+            transform = Mat4::rotation_z(self.number(value)?) * transform;
+        }
+        if let Some(value) = table.get("rotate") {
+            transform = Mat4::from_quat(&self.rotation(value)?) * transform;
+        }
+        if let Some(value) = table.get("look_at") {
+            let look_at = self.table(value)?;
+            self.check_keys(&look_at, &["from", "to", "up"])?;
+            let from = self.vec3_field(&look_at, "from")?;
+            let to = self.vec3_field(&look_at, "to")?;
+            let up = match look_at.get("up") {
+                Some(value) => self.vec3(value)?,
+                None => Vec3::new(0.0, 1.0, 0.0),
+            };
+            if Vec3::cross(&up, &(to - from)).near_zero() {
+                return Err(self.error(
+                    value.span(),
+                    "`from` and `to` must differ and not be lined up with `up`".to_string(),
+                ));
+            }
+            transform = Mat4::look_at(&from, &to, &up) * transform;
+        }
+        if let Some(value) = table.get("translate") {
+            transform = Mat4::translation(&self.vec3(value)?) * transform;
+        }
+        Ok(transform)
+    }
+
+    fn scale(&self, value: &Value) -> Result<Vec3, SceneError> {
+        let factors = match value.get_ref() {
+            DeValue::Array(_) => self.vec3(value)?,
+            _ => {
+                let factor = self.number(value)?;
+                Vec3::new(factor, factor, factor)
+            }
+        };
+        if factors.x() == 0.0 || factors.y() == 0.0 || factors.z() == 0.0 {
+            return Err(self.error(value.span(), "scale must be non-zero".to_string()));
+        }
+        Ok(factors)
+    }
+
+    // Euler angles in degrees, `{ axis, angle }` or `{ quaternion = [x, y, z, w] }`.
+    fn rotation(&self, value: &Value) -> Result<Quat, SceneError> {
+        if let DeValue::Array(_) = value.get_ref() {
+            return Ok(Quat::from_euler(&self.vec3(value)?));
+        }
+
+        let table = self.table(value)?;
+        if let Some(quaternion) = table.get("quaternion") {
+            self.check_keys(&table, &["quaternion"])?;
+            let components = self.array(quaternion)?;
+            if components.len() != 4 {
+                return Err(self.error(
+                    quaternion.span(),
+                    format!("expected 4 components, found {}", components.len()),
+                ));
+            }
+            let mut q = [0.0; 4];
+            for (slot, component) in q.iter_mut().zip(components) {
+                *slot = self.number(component)?;
+            }
+            let q = Quat::new(q[0], q[1], q[2], q[3]);
+            if q.length() == 0.0 {
+                return Err(
+                    self.error(quaternion.span(), "quaternion must be non-zero".to_string())
+                );
+            }
+            return Ok(q);
+        }
+
+        self.check_keys(&table, &["axis", "angle", "quaternion"])?;
+        let value = self.field(&table, "axis")?;
+        let axis = self.vec3(value)?;
+        if axis.near_zero() {
+            return Err(self.error(value.span(), "axis must be non-zero".to_string()));
+        }
+        Ok(Quat::from_axis_angle(
+            &axis,
+            self.number_field(&table, "angle")?,
+        ))
+    }
+
+    fn mesh(&mut self, table: &Table) -> Result<Arc<dyn Hittable>, SceneError> {
+        self.check_keys(
+            table,
+            &[
+                "type",
+                "positions",
+                "position_samples",
+                "time0",
+                "time1",
+                "normals",
+                "uvs",
+                "colors",
+                "indices",
+                "material",
+            ],
+        )?;
+
+        // A deforming mesh lists its positions at evenly spaced times
+        // between `time0` and `time1` instead of a single `positions` array.
+        let mut position_samples = vec![];
+        match (table.get("positions"), table.get("position_samples")) {
+            (Some(_), Some(value)) => {
+                return Err(self.error(
+                    value.span(),
+                    "`positions` cannot be combined with `position_samples`".to_string(),
+                ))
+            }
+            (Some(value), None) => position_samples.push(self.positions(value)?),
+            (None, Some(value)) => {
+                for sample in self.array(value)? {
+                    let positions = self.positions(sample)?;
+                    if let Some(first) = position_samples.first() {
+                        let expected = first.len();
+                        if positions.len() != expected {
+                            return Err(self.error(
+                                sample.span(),
+                                format!(
+                                    "expected {} positions, found {}",
+                                    expected,
+                                    positions.len()
+                                ),
+                            ));
+                        }
+                    }
+                    position_samples.push(positions);
+                }
+                if position_samples.len() < 2 {
+                    return Err(self.error(
+                        value.span(),
+                        "expected at least 2 position samples".to_string(),
+                    ));
+                }
+            }
+            (None, None) => {
+                return Err(self.error(table.span.clone(), "missing field `positions`".to_string()))
+            }
+        }
+        let positions = &position_samples[0];
+
+        let mut normals = vec![];
+        if let Some(value) = table.get("normals") {
+            for normal in self.array(value)? {
+                normals.push(self.vec3(normal)?);
+            }
+            if normals.len() != positions.len() {
+                return Err(self.error(
+                    value.span(),
+                    format!(
+                        "expected {} normals, found {}",
+                        positions.len(),
+                        normals.len()
+                    ),
+                ));
+            }
+        }
+
+        let mut uvs = vec![];
+        if let Some(value) = table.get("uvs") {
+            for uv in self.array(value)? {
+                let pair = self.array(uv)?;
+                if pair.len() != 2 {
+                    return Err(self.error(uv.span(), "expected [u, v]".to_string()));
+                }
+                uvs.push((self.number(&pair[0])?, self.number(&pair[1])?));
+            }
+            if uvs.len() != positions.len() {
+                return Err(self.error(
+                    value.span(),
+                    format!("expected {} uvs, found {}", positions.len(), uvs.len()),
+                ));
+            }
+        }
+
+        let mut colors = vec![];
+        if let Some(value) = table.get("colors") {
+            for color in self.array(value)? {
+                colors.push(self.vec3(color)?);
+            }
+            if colors.len() != positions.len() {
+                return Err(self.error(
+                    value.span(),
+                    format!(
+                        "expected {} colors, found {}",
+                        positions.len(),
+                        colors.len()
+                    ),
+                ));
+            }
+        }
+
+        let mut indices = vec![];
+        for value in self.array(self.field(table, "indices")?)? {
+            let triangle = self.array(value)?;
+            if triangle.len() != 3 {
+                return Err(self.error(value.span(), "expected 3 vertex indices".to_string()));
+            }
+            let mut face = [0; 3];
+            for (slot, index) in face.iter_mut().zip(triangle.iter()) {
+                let parsed = match index.get_ref() {
+                    DeValue::Integer(i) => i.as_str().parse::<usize>().ok(),
+                    _ => None,
+                };
+                *slot = match parsed {
+                    Some(i) if i < positions.len() => i,
+                    _ => {
+                        return Err(self.error(
+                            index.span(),
+                            format!("expected a vertex index below {}", positions.len()),
+                        ))
+                    }
+                };
+            }
+            indices.push(face);
+        }
+
+        let mat = self.material_ref(self.field(table, "material")?)?;
+        if position_samples.len() == 1 {
+            return Ok(Arc::new(TriangleMesh::with_colors(
+                position_samples.remove(0),
+                normals,
+                uvs,
+                colors,
+                indices,
+                mat,
+                &self.bvh_builder,
+            )));
+        }
+        if let Some(value) = table.get("colors") {
+            return Err(self.error(
+                value.span(),
+                "`colors` cannot be combined with `position_samples`".to_string(),
+            ));
+        }
+
+        let time0 = match table.get("time0") {
+            Some(value) => self.number(value)?,
+            None => 0.0,
+        };
+        let time1 = match table.get("time1") {
+            Some(value) => self.number(value)?,
+            None => 1.0,
+        };
+        if time1 <= time0 {
+            return Err(self.error(
+                table.span.clone(),
+                "`time1` must be greater than `time0`".to_string(),
+            ));
+        }
+        Ok(Arc::new(TriangleMesh::with_position_samples(
+            position_samples,
+            time0,
+            time1,
+            normals,
+            uvs,
+            indices,
+            mat,
+            &self.bvh_builder,
+        )))
+    }
+
+    fn positions(&self, value: &Value) -> Result<Vec<Point3>, SceneError> {
+        let mut positions = vec![];
+        for value in self.array(value)? {
+            positions.push(self.vec3(value)?);
+        }
+        Ok(positions)
+    }
+}
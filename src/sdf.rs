@@ -0,0 +1,343 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+const MAX_STEPS: usize = 256;
+// Distance at which the march counts as having reached the surface.
+const HIT_EPSILON: f32 = 0.0001;
+// Offset for the central differences that give the surface normal.
+const NORMAL_EPSILON: f32 = 0.0005;
+
+// A signed distance function: negative inside, positive outside, and never
+// more than the true distance to the surface times `lipschitz`.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: &Point3) -> f32;
+
+    // How much faster than the true distance the function may grow for
+    // points inside `bounds`; the march shortens its steps by this factor.
+    fn lipschitz(&self, _bounds: &AABB) -> f32 {
+        1.0
+    }
+}
+
+fn max_component(v: &Vec3) -> f32 {
+    f32::max(v.x(), f32::max(v.y(), v.z()))
+}
+
+fn clamped_length(v: &Vec3) -> f32 {
+    Vec3::new(v.x().max(0.0), v.y().max(0.0), v.z().max(0.0)).length()
+}
+
+pub struct SdfSphere {
+    center: Point3,
+    radius: f32,
+}
+
+impl SdfSphere {
+    pub fn new(center: Point3, radius: f32) -> SdfSphere {
+        SdfSphere { center, radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Point3) -> f32 {
+        (*p - self.center).length() - self.radius
+    }
+}
+
+// An axis-aligned box with the given half extents, its edges rounded off by
+// `radius` without growing the box.
+pub struct SdfBox {
+    center: Point3,
+    half_size: Vec3,
+    radius: f32,
+}
+
+impl SdfBox {
+    pub fn new(center: Point3, half_size: Vec3) -> SdfBox {
+        SdfBox::rounded(center, half_size, 0.0)
+    }
+
+    pub fn rounded(center: Point3, half_size: Vec3, radius: f32) -> SdfBox {
+        SdfBox {
+            center,
+            half_size,
+            radius,
+        }
+    }
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, p: &Point3) -> f32 {
+        let local = *p - self.center;
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let q =
+            Vec3::new(local.x().abs(), local.y().abs(), local.z().abs()) - self.half_size + radius;
+        clamped_length(&q) + max_component(&q).min(0.0) - self.radius
+    }
+}
+
+// A ring around the vertical axis through `center`, as `Torus`.
+pub struct SdfTorus {
+    center: Point3,
+    major_radius: f32,
+    minor_radius: f32,
+}
+
+impl SdfTorus {
+    pub fn new(center: Point3, major_radius: f32, minor_radius: f32) -> SdfTorus {
+        SdfTorus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p: &Point3) -> f32 {
+        let local = *p - self.center;
+        let ring = (local.x() * local.x() + local.z() * local.z()).sqrt() - self.major_radius;
+        (ring * ring + local.y() * local.y()).sqrt() - self.minor_radius
+    }
+}
+
+// All points within `radius` of the segment from `a` to `b`.
+pub struct SdfCapsule {
+    a: Point3,
+    b: Point3,
+    radius: f32,
+}
+
+impl SdfCapsule {
+    pub fn new(a: Point3, b: Point3, radius: f32) -> SdfCapsule {
+        SdfCapsule { a, b, radius }
+    }
+}
+
+impl Sdf for SdfCapsule {
+    fn distance(&self, p: &Point3) -> f32 {
+        let pa = *p - self.a;
+        let ba = self.b - self.a;
+        let h = (Vec3::dot(&pa, &ba) / Vec3::dot(&ba, &ba)).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+}
+
+// Polynomial smooth minimum after Quilez; `k` is the width of the blend.
+pub struct SmoothUnion {
+    left: Arc<dyn Sdf>,
+    right: Arc<dyn Sdf>,
+    k: f32,
+}
+
+impl SmoothUnion {
+    pub fn new(left: Arc<dyn Sdf>, right: Arc<dyn Sdf>, k: f32) -> SmoothUnion {
+        SmoothUnion { left, right, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Point3) -> f32 {
+        let d1 = self.left.distance(p);
+        let d2 = self.right.distance(p);
+        if self.k <= 0.0 {
+            return d1.min(d2);
+        }
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+
+    fn lipschitz(&self, bounds: &AABB) -> f32 {
+        f32::max(self.left.lipschitz(bounds), self.right.lipschitz(bounds))
+    }
+}
+
+// `left` with `right` carved out of it, blended over a width of `k`.
+pub struct SmoothSubtraction {
+    left: Arc<dyn Sdf>,
+    right: Arc<dyn Sdf>,
+    k: f32,
+}
+
+impl SmoothSubtraction {
+    pub fn new(left: Arc<dyn Sdf>, right: Arc<dyn Sdf>, k: f32) -> SmoothSubtraction {
+        SmoothSubtraction { left, right, k }
+    }
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, p: &Point3) -> f32 {
+        let d1 = self.left.distance(p);
+        let d2 = -self.right.distance(p);
+        if self.k <= 0.0 {
+            return d1.max(d2);
+        }
+        let h = (0.5 - 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h + self.k * h * (1.0 - h)
+    }
+
+    fn lipschitz(&self, bounds: &AABB) -> f32 {
+        f32::max(self.left.lipschitz(bounds), self.right.lipschitz(bounds))
+    }
+}
+
+// Copies of the shape every `period` units along each axis, folded into the
+// cell around the origin. A zero component leaves that axis unrepeated. The
+// shape should fit inside one cell, or neighbouring copies get clipped.
+pub struct Repeat {
+    shape: Arc<dyn Sdf>,
+    period: Vec3,
+}
+
+impl Repeat {
+    pub fn new(shape: Arc<dyn Sdf>, period: Vec3) -> Repeat {
+        Repeat { shape, period }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: &Point3) -> f32 {
+        let mut q = *p;
+        for c in 0..3 {
+            if self.period[c] > 0.0 {
+                q[c] -= self.period[c] * (q[c] / self.period[c]).round();
+            }
+        }
+        self.shape.distance(&q)
+    }
+
+    fn lipschitz(&self, bounds: &AABB) -> f32 {
+        self.shape.lipschitz(bounds)
+    }
+}
+
+// Rotates the shape about the y axis by `rate` degrees per unit of height.
+pub struct Twist {
+    shape: Arc<dyn Sdf>,
+    rate: f32,
+}
+
+impl Twist {
+    pub fn new(shape: Arc<dyn Sdf>, rate: f32) -> Twist {
+        Twist {
+            shape,
+            rate: rate.to_radians(),
+        }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: &Point3) -> f32 {
+        let (sin, cos) = (self.rate * p.y()).sin_cos();
+        let q = Point3::new(cos * p.x() + sin * p.z(), p.y(), -sin * p.x() + cos * p.z());
+        self.shape.distance(&q)
+    }
+
+    // Twisting stretches space most far from the axis.
+    fn lipschitz(&self, bounds: &AABB) -> f32 {
+        let (min, max) = (bounds.min(), bounds.max());
+        let x = f32::max(min.x().abs(), max.x().abs());
+        let z = f32::max(min.z().abs(), max.z().abs());
+        let stretch = self.rate * (x * x + z * z).sqrt();
+        self.shape.lipschitz(bounds) * (1.0 + stretch * stretch).sqrt()
+    }
+}
+
+// A hittable that sphere-traces an SDF tree. Distance functions have no
+// natural extent, so the caller gives the box that encloses the surface;
+// marching is limited to it and `BvhNode` uses it as the bounding box.
+pub struct SdfObject {
+    sdf: Arc<dyn Sdf>,
+    bounds: AABB,
+    mat: Arc<dyn Material>,
+    step_scale: f32,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Arc<dyn Sdf>, bounds: AABB, mat: Arc<dyn Material>) -> SdfObject {
+        let step_scale = 1.0 / sdf.lipschitz(&bounds);
+        SdfObject {
+            sdf,
+            bounds,
+            mat,
+            step_scale,
+        }
+    }
+
+    // The part of the ray between `t_min` and `t_max` inside the bounds.
+    fn clip(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for c in 0..3 {
+            let inv_dir = 1.0 / r.direction()[c];
+            let mut near = (self.bounds.min()[c] - r.origin()[c]) * inv_dir;
+            let mut far = (self.bounds.max()[c] - r.origin()[c]) * inv_dir;
+            if inv_dir < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    fn normal(&self, p: &Point3) -> Vec3 {
+        let mut gradient = Vec3::default();
+        for c in 0..3 {
+            let mut offset = Vec3::default();
+            offset[c] = NORMAL_EPSILON;
+            gradient[c] = self.sdf.distance(&(*p + offset)) - self.sdf.distance(&(*p - offset));
+        }
+        Vec3::unit_vector(&gradient)
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let (mut t, t_end) = match self.clip(r, t_min, t_max) {
+            Some(range) => range,
+            None => return false,
+        };
+
+        // Distances are in world units, steps in units of the ray parameter.
+        // Rays that start inside the shape march on the absolute distance
+        // until they reach the surface from within.
+        let inv_length = 1.0 / r.direction().length();
+        for _ in 0..MAX_STEPS {
+            let distance = self.sdf.distance(&r.at(t)).abs();
+            if distance < HIT_EPSILON {
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.set_face_normal(r, &self.normal(&rec.p));
+                // There is no natural parameterization; textures should
+                // rely on the hit point instead.
+                rec.u = 0.0;
+                rec.v = 0.0;
+                rec.mat = Arc::clone(&self.mat);
+                return true;
+            }
+
+            t += distance * self.step_scale * inv_length;
+            if t > t_end {
+                break;
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.bounds;
+        true
+    }
+}
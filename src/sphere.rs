@@ -37,13 +37,25 @@ impl Sphere {
         Arc::clone(&self.mat)
     }
 
-    fn get_sphere_uv(p: &Point3, u: &mut f32, v: &mut f32) {
+    pub(crate) fn get_sphere_uv(p: &Point3, u: &mut f32, v: &mut f32) {
         let theta = f32::acos(-p.y());
         let phi = f32::atan2(-p.z(), p.x()) + PI;
 
         *u = phi / (2.0 * PI);
         *v = theta / PI;
     }
+
+    // Derivatives of the point along u and v, which vanish at the poles.
+    pub(crate) fn get_sphere_frame(n: &Vec3, radius: f32) -> (Vec3, Vec3) {
+        let around = Vec3::new(n.z(), 0.0, -n.x());
+        if around.near_zero() {
+            return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        }
+        (
+            2.0 * PI * radius * around,
+            PI * radius.abs() * Vec3::cross(n, &Vec3::unit_vector(&around)),
+        )
+    }
 }
 
 impl Hittable for Sphere {
@@ -78,6 +90,7 @@ impl Hittable for Sphere {
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
         Sphere::get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+        (rec.tangent, rec.bitangent) = Sphere::get_sphere_frame(&outward_normal, self.radius);
         rec.mat = Arc::clone(&self.mat);
 
         true
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{bvh::BvhBuilder, material::Material, triangle::TriangleMesh, vec3::Point3};
+
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_FACET_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Data {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl Display for StlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StlError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            StlError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            StlError::Data { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for StlError {}
+
+// STL stores every facet with its own copies of the corners; shared corners
+// are merged so the mesh has one vertex per distinct position.
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    indices: Vec<[usize; 3]>,
+    vertex_map: HashMap<[u32; 3], usize>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, p: [f32; 3]) -> usize {
+        let key = [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+        let positions = &mut self.positions;
+        *self.vertex_map.entry(key).or_insert_with(|| {
+            positions.push(Point3::new(p[0], p[1], p[2]));
+            positions.len() - 1
+        })
+    }
+
+    fn facet(&mut self, corners: &[[f32; 3]]) {
+        let indices: Vec<usize> = corners.iter().map(|&p| self.vertex(p)).collect();
+        for i in 1..indices.len() - 1 {
+            self.indices.push([indices[0], indices[i], indices[i + 1]]);
+        }
+    }
+
+    fn build(self, mat: Arc<dyn Material>, bvh_builder: &BvhBuilder) -> TriangleMesh {
+        TriangleMesh::new(
+            self.positions,
+            vec![],
+            vec![],
+            self.indices,
+            mat,
+            bvh_builder,
+        )
+    }
+}
+
+fn load_binary(bytes: &[u8]) -> MeshBuilder {
+    let float = |offset: usize| {
+        f32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    };
+
+    let mut builder = MeshBuilder::default();
+    let mut offset = BINARY_HEADER_SIZE;
+    while offset + BINARY_FACET_SIZE <= bytes.len() {
+        // The stored facet normal comes first; it is often stale, so the
+        // winding of the corners decides the normal instead.
+        let corner = |i: usize| {
+            let start = offset + 12 + 12 * i;
+            [float(start), float(start + 4), float(start + 8)]
+        };
+        builder.facet(&[corner(0), corner(1), corner(2)]);
+        offset += BINARY_FACET_SIZE;
+    }
+    builder
+}
+
+fn load_ascii(path: &Path, source: &str) -> Result<MeshBuilder, StlError> {
+    let error = |line: usize, message: String| StlError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut builder = MeshBuilder::default();
+    let mut in_solid = false;
+    let mut facet: Option<Vec<[f32; 3]>> = None;
+    let mut in_loop = false;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        match keyword {
+            "solid" if !in_solid => in_solid = true,
+            "endsolid" if in_solid && facet.is_none() => in_solid = false,
+            "facet" if in_solid && facet.is_none() => facet = Some(vec![]),
+            "outer" if facet.is_some() && !in_loop => {
+                if args != ["loop"] {
+                    return Err(error(line_number, "expected `outer loop`".to_string()));
+                }
+                in_loop = true;
+            }
+            "vertex" if in_loop => {
+                let coordinates = args
+                    .iter()
+                    .map(|arg| arg.parse::<f32>().ok())
+                    .collect::<Option<Vec<f32>>>();
+                match coordinates.as_deref() {
+                    Some(&[x, y, z]) => facet.as_mut().unwrap().push([x, y, z]),
+                    _ => return Err(error(line_number, "vertex needs three numbers".to_string())),
+                }
+            }
+            "endloop" if in_loop => in_loop = false,
+            "endfacet" if facet.is_some() && !in_loop => {
+                let corners = facet.take().unwrap();
+                if corners.len() < 3 {
+                    return Err(error(
+                        line_number,
+                        format!("facet needs at least 3 vertices, found {}", corners.len()),
+                    ));
+                }
+                builder.facet(&corners);
+            }
+            "solid" | "endsolid" | "facet" | "outer" | "vertex" | "endloop" | "endfacet" => {
+                return Err(error(line_number, format!("unexpected `{}`", keyword)))
+            }
+            _ => return Err(error(line_number, format!("unknown keyword `{}`", keyword))),
+        }
+    }
+
+    if in_solid {
+        return Err(error(
+            source.lines().count(),
+            "file ends inside a solid".to_string(),
+        ));
+    }
+
+    Ok(builder)
+}
+
+// Loads an STL mesh. Binary files are recognized by their size matching the
+// triangle count in the header, since binary headers may also start with
+// `solid`; anything else has to be ASCII STL.
+pub fn load_stl(
+    path: &str,
+    mat: Arc<dyn Material>,
+    bvh_builder: &BvhBuilder,
+) -> Result<TriangleMesh, StlError> {
+    let path = Path::new(path);
+    let bytes = fs::read(path).map_err(|source| StlError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    // Only consulted when the file is not ASCII either.
+    let binary_mismatch = if bytes.len() >= BINARY_HEADER_SIZE {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        let expected = BINARY_HEADER_SIZE + BINARY_FACET_SIZE * count;
+        if bytes.len() == expected {
+            return Ok(load_binary(&bytes).build(mat, bvh_builder));
+        }
+        format!(
+            "binary STL header announces {} triangles ({} bytes), but the file has {} bytes",
+            count,
+            expected,
+            bytes.len()
+        )
+    } else {
+        format!("too short for a binary STL header ({} bytes)", bytes.len())
+    };
+
+    let source = match std::str::from_utf8(&bytes) {
+        Ok(source) if source.trim_start().starts_with("solid") => source,
+        _ => {
+            return Err(StlError::Data {
+                path: path.to_path_buf(),
+                message: format!("not ASCII STL, and {}", binary_mismatch),
+            })
+        }
+    };
+    Ok(load_ascii(path, source)?.build(mat, bvh_builder))
+}
@@ -1,13 +1,21 @@
 use std::{fmt::Debug, sync::Arc};
 
 use crate::{
+    color::srgb_eotf,
+    hittable::HitRecord,
     perlin::Perlin,
     vec3::{Color, Point3},
 };
-use image::io::Reader as ImageReader;
+use image::ImageError;
 
 pub trait Texture: Debug + Send + Sync {
     fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+
+    // Materials look textures up through the whole hit record, so textures
+    // can use surface data beyond the texture coordinates.
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
 }
 #[derive(Debug)]
 pub struct SolidColor {
@@ -91,12 +99,39 @@ impl Texture for NoiseTexture {
     }
 }
 
+// How texture coordinates outside [0, 1] are brought back into the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureWrap {
+    Clamp,
+    Repeat,
+    MirroredRepeat,
+}
+
+impl TextureWrap {
+    fn apply(self, c: f32) -> f32 {
+        match self {
+            TextureWrap::Clamp => c.clamp(0.0, 1.0),
+            TextureWrap::Repeat => c.rem_euclid(1.0),
+            TextureWrap::MirroredRepeat => {
+                let c = c.rem_euclid(2.0);
+                if c > 1.0 {
+                    2.0 - c
+                } else {
+                    c
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ImageTexture {
     width: u32,
     height: u32,
     bytes_per_scanline: u32,
     data: Vec<u8>,
+    wrap: TextureWrap,
+    srgb: bool,
 }
 
 impl ImageTexture {
@@ -108,36 +143,63 @@ impl ImageTexture {
             height: 0,
             bytes_per_scanline: 0,
             data: vec![],
+            wrap: TextureWrap::Clamp,
+            srgb: false,
         }
     }
 
-    pub fn new(filename: String) -> ImageTexture {
-        let mut image_option: Option<_> = None;
-
-        match ImageReader::open(filename.clone()) {
-            Ok(buffer) => match buffer.decode() {
-                Ok(image) => {
-                    image_option = Some(image);
-                }
-                _ => {}
-            },
-            _ => {}
+    // Tightly packed 8-bit RGB rows, top row first. Color images are usually
+    // sRGB-encoded and are decoded to linear values on lookup; data such as
+    // roughness or normal maps is stored linearly.
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        wrap: TextureWrap,
+        srgb: bool,
+    ) -> ImageTexture {
+        assert_eq!(
+            data.len(),
+            (width * height * ImageTexture::bytes_per_pixel) as usize,
+            "image data needs three bytes per pixel"
+        );
+        ImageTexture {
+            width,
+            height,
+            bytes_per_scanline: width * ImageTexture::bytes_per_pixel,
+            data,
+            wrap,
+            srgb,
         }
+    }
+
+    // `srgb` as for `from_pixels`: set for color images, clear for data.
+    pub fn load(filename: &str, srgb: bool) -> Result<ImageTexture, ImageError> {
+        let image = image::open(filename)?.into_rgb8();
+        let (width, height) = image.dimensions();
+        Ok(ImageTexture::from_pixels(
+            width,
+            height,
+            image.into_raw(),
+            TextureWrap::Clamp,
+            srgb,
+        ))
+    }
 
-        match image_option {
-            Some(image) => ImageTexture {
-                width: image.width(),
-                height: image.height(),
-                bytes_per_scanline: image.width() * ImageTexture::bytes_per_pixel,
-                data: image.into_bytes().to_vec(),
-            },
-            _ => {
+    // Missing or unreadable images render as cyan rather than failing; use
+    // `load` to handle the error instead.
+    pub fn new(filename: String, srgb: bool) -> ImageTexture {
+        match ImageTexture::load(&filename, srgb) {
+            Ok(texture) => texture,
+            Err(_) => {
                 eprintln!("ERROR: Could not load texture image file {}", filename);
                 ImageTexture {
                     width: 0,
                     height: 0,
                     bytes_per_scanline: 0,
                     data: vec![],
+                    wrap: TextureWrap::Clamp,
+                    srgb: false,
                 }
             }
         }
@@ -149,8 +211,8 @@ impl Texture for ImageTexture {
         if self.data.len() == 0 {
             Color::new(0.0, 1.0, 1.0)
         } else {
-            let u = u.clamp(0.0, 1.0);
-            let v = 1.0 - v.clamp(0.0, 1.0);
+            let u = self.wrap.apply(u);
+            let v = 1.0 - self.wrap.apply(v);
 
             let mut i = (u * (self.width as f32)) as u32;
             let mut j = (v * (self.height as f32)) as u32;
@@ -165,11 +227,76 @@ impl Texture for ImageTexture {
             let color_scale = 1.0 / 255.0;
             let pixel_index = j * self.bytes_per_scanline + i * ImageTexture::bytes_per_pixel;
 
-            Color::new(
+            let color = Color::new(
                 color_scale * self.data[pixel_index as usize] as f32,
                 color_scale * self.data[(pixel_index + 1) as usize] as f32,
                 color_scale * self.data[(pixel_index + 2) as usize] as f32,
-            )
+            );
+            if self.srgb {
+                Color::new(
+                    srgb_eotf(color.x()),
+                    srgb_eotf(color.y()),
+                    srgb_eotf(color.z()),
+                )
+            } else {
+                color
+            }
         }
     }
 }
+
+// The interpolated vertex color of mesh hits, falling back to another
+// texture on surfaces without vertex colors.
+#[derive(Debug)]
+pub struct VertexColorTexture {
+    fallback: Arc<dyn Texture>,
+}
+
+impl VertexColorTexture {
+    pub fn new(fallback: Arc<dyn Texture>) -> VertexColorTexture {
+        VertexColorTexture { fallback }
+    }
+
+    pub fn with_color(c: Color) -> VertexColorTexture {
+        VertexColorTexture {
+            fallback: Arc::new(SolidColor::new(c)),
+        }
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.fallback.value(u, v, p)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        match rec.vertex_color {
+            Some(color) => color,
+            None => self.fallback.value_at(rec),
+        }
+    }
+}
+
+// Another texture multiplied by a constant color, as glTF factors scale
+// their textures.
+#[derive(Debug)]
+pub struct ScaledTexture {
+    texture: Arc<dyn Texture>,
+    scale: Color,
+}
+
+impl ScaledTexture {
+    pub fn new(texture: Arc<dyn Texture>, scale: Color) -> ScaledTexture {
+        ScaledTexture { texture, scale }
+    }
+}
+
+impl Texture for ScaledTexture {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.scale * self.texture.value(u, v, p)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.scale * self.texture.value_at(rec)
+    }
+}
@@ -27,6 +27,10 @@ impl ThreadPool {
         }
     }
 
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
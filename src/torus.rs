@@ -0,0 +1,224 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    quadric::sweep_angle,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+const EPSILON: f64 = 1e-9;
+
+// Real roots of x^3 + a x^2 + b x + c, after Schwarze, "Cubic and Quartic
+// Roots" (Graphics Gems I).
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if d.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.0).cos(),
+            -t * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+fn solve_quadratic(b: f64, c: f64, roots: &mut Vec<f64>) {
+    let discriminant = b * b - 4.0 * c;
+    if discriminant.abs() < EPSILON {
+        roots.push(-0.5 * b);
+    } else if discriminant > 0.0 {
+        let sqrt_d = discriminant.sqrt();
+        roots.push(0.5 * (-b - sqrt_d));
+        roots.push(0.5 * (-b + sqrt_d));
+    }
+}
+
+// Real roots of x^4 + a x^3 + b x^2 + c x + d via Ferrari's resolvent
+// cubic, each polished with a few Newton steps on the original polynomial.
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Substitute x = y - a/4 to remove the cubic term.
+    let sq_a = a * a;
+    let p = b - 3.0 / 8.0 * sq_a;
+    let q = c - a * b / 2.0 + sq_a * a / 8.0;
+    let r = d - a * c / 4.0 + sq_a * b / 16.0 - 3.0 / 256.0 * sq_a * sq_a;
+
+    let mut roots = Vec::with_capacity(4);
+    if r.abs() < EPSILON {
+        roots.push(0.0);
+        roots.extend(solve_cubic(0.0, p, q));
+    } else {
+        let z = solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -EPSILON || v < -EPSILON {
+            return roots;
+        }
+        let u = u.max(0.0).sqrt();
+        let v = v.max(0.0).sqrt().copysign(q);
+
+        solve_quadratic(v, z - u, &mut roots);
+        solve_quadratic(-v, z + u, &mut roots);
+    }
+
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df != 0.0 {
+                *root = x - f / df;
+            }
+        }
+    }
+    roots
+}
+
+// A ring around the vertical axis through `center`: the tube of
+// `minor_radius` follows a circle of `major_radius` in the xz plane.
+pub struct Torus {
+    center: Point3,
+    major_radius: f32,
+    minor_radius: f32,
+    phi_max: f32,
+    mat: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        major_radius: f32,
+        minor_radius: f32,
+        mat: Arc<dyn Material>,
+    ) -> Torus {
+        Torus::with_sweep(center, major_radius, minor_radius, 360.0, mat)
+    }
+
+    // Only the part of the ring from +x towards +z within `sweep` degrees.
+    pub fn with_sweep(
+        center: Point3,
+        major_radius: f32,
+        minor_radius: f32,
+        sweep: f32,
+        mat: Arc<dyn Material>,
+    ) -> Torus {
+        assert!(
+            major_radius >= 0.0 && minor_radius > 0.0,
+            "a torus needs a positive minor radius and a non-negative major radius"
+        );
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            phi_max: sweep.clamp(0.0, 360.0).to_radians(),
+            mat,
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let major = self.major_radius as f64;
+        let minor = self.minor_radius as f64;
+        let oc = r.origin() - self.center;
+        let dir = r.direction();
+        let length = dir.length() as f64;
+        let d = [
+            dir.x() as f64 / length,
+            dir.y() as f64 / length,
+            dir.z() as f64 / length,
+        ];
+        let mut o = [oc.x() as f64, oc.y() as f64, oc.z() as f64];
+
+        // Start from where the ray enters the bounding sphere; the quartic
+        // loses precision quickly when the origin is far away.
+        let bound = major + minor;
+        let half_b = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let c = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] - bound * bound;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let shift = -half_b - discriminant.sqrt();
+        for (oi, di) in o.iter_mut().zip(d) {
+            *oi += shift * di;
+        }
+
+        let k = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let g = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        let mut roots = solve_quartic(
+            4.0 * k,
+            4.0 * k * k + 2.0 * g - four_r2 * (d[0] * d[0] + d[2] * d[2]),
+            4.0 * k * g - 2.0 * four_r2 * (o[0] * d[0] + o[2] * d[2]),
+            g * g - four_r2 * (o[0] * o[0] + o[2] * o[2]),
+        );
+        roots.sort_by(f64::total_cmp);
+
+        for s in roots {
+            let t = ((shift + s) / length) as f32;
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let p = oc + t * dir;
+            let phi = sweep_angle(&p);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            let rho = (p.x() * p.x() + p.z() * p.z()).sqrt();
+            let ring = Vec3::new(p.x(), 0.0, p.z()) * (self.major_radius / rho);
+            let outward_normal = Vec3::unit_vector(&(p - ring));
+            let theta = f32::atan2(p.y(), rho - self.major_radius);
+
+            rec.t = t;
+            rec.p = r.at(t);
+            rec.set_face_normal(r, &outward_normal);
+            rec.u = phi / self.phi_max;
+            rec.v = (theta + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+            rec.tangent = self.phi_max * Vec3::new(-p.z(), 0.0, p.x());
+            rec.bitangent = 2.0
+                * std::f32::consts::PI
+                * ((rho - self.major_radius) * Vec3::new(0.0, 1.0, 0.0)
+                    - (p.y() / rho) * Vec3::new(p.x(), 0.0, p.z()));
+            rec.mat = Arc::clone(&self.mat);
+            return true;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        let extent = Vec3::new(
+            self.major_radius + self.minor_radius,
+            self.minor_radius,
+            self.major_radius + self.minor_radius,
+        );
+        *output_box = AABB::new(&(self.center - extent), &(self.center + extent));
+        true
+    }
+}
@@ -0,0 +1,492 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::{surrounding_box, AABB},
+    bvh::{BvhBuilder, BvhNode},
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    material::Material,
+    ray::Ray,
+    vec3::{Color, Point3, Vec3},
+};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f32, f32); 3]>,
+    mat: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            mat,
+        }
+    }
+
+    pub fn with_attributes(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f32, f32); 3]>,
+        mat: Arc<dyn Material>,
+    ) -> Triangle {
+        Triangle {
+            vertices,
+            normals,
+            uvs,
+            mat,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let (t, b1, b2) = match intersect_triangle(r, &self.vertices, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        set_triangle_record(
+            r,
+            t,
+            (b1, b2),
+            &self.vertices,
+            self.normals.as_ref(),
+            self.uvs.as_ref(),
+            None,
+            &self.mat,
+            rec,
+        );
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = triangle_box(&self.vertices);
+        true
+    }
+}
+
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    // Further position samples of a deforming mesh. Together with
+    // `positions` they are spread evenly over time0..time1.
+    pub position_samples: Vec<Vec<Point3>>,
+    pub time0: f32,
+    pub time1: f32,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    // One color per vertex, or empty.
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+    pub mat: Arc<dyn Material>,
+}
+
+impl MeshData {
+    // Attributes are empty or hold one entry per vertex, and every index
+    // names a vertex.
+    fn check(&self) {
+        let vertices = self.positions.len();
+        assert!(
+            self.position_samples.iter().all(|p| p.len() == vertices),
+            "every position sample needs one position per vertex"
+        );
+        assert!(
+            self.normals.is_empty() || self.normals.len() == vertices,
+            "vertex normals need one normal per vertex"
+        );
+        assert!(
+            self.uvs.is_empty() || self.uvs.len() == vertices,
+            "texture coordinates need one pair per vertex"
+        );
+        assert!(
+            self.colors.is_empty() || self.colors.len() == vertices,
+            "vertex colors need one color per vertex"
+        );
+        if let Some(index) = self.indices.iter().flatten().find(|&&i| i >= vertices) {
+            panic!("vertex index {} out of range (have {})", index, vertices);
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_deforming(&self) -> bool {
+        !self.position_samples.is_empty()
+    }
+
+    fn sample(&self, sample: usize) -> &[Point3] {
+        match sample {
+            0 => &self.positions,
+            _ => &self.position_samples[sample - 1],
+        }
+    }
+
+    fn vertices_of(&self, sample: usize, index: usize) -> [Point3; 3] {
+        let positions = self.sample(sample);
+        let [i0, i1, i2] = self.indices[index];
+        [positions[i0], positions[i1], positions[i2]]
+    }
+
+    fn vertices(&self, index: usize, time: f32) -> [Point3; 3] {
+        if !self.is_deforming() {
+            return self.vertices_of(0, index);
+        }
+
+        let segments = self.position_samples.len();
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0) * segments as f32;
+        let sample = (s as usize).min(segments - 1);
+        let t = s - sample as f32;
+
+        let a = self.vertices_of(sample, index);
+        let b = self.vertices_of(sample + 1, index);
+        [
+            a[0] + t * (b[0] - a[0]),
+            a[1] + t * (b[1] - a[1]),
+            a[2] + t * (b[2] - a[2]),
+        ]
+    }
+
+    // Vertices move linearly between samples, so the boxes of the samples
+    // enclose the whole motion, as with MovingSphere's two boxes.
+    fn triangle_box(&self, index: usize) -> AABB {
+        let mut output_box = triangle_box(&self.vertices_of(0, index));
+        for sample in 1..=self.position_samples.len() {
+            output_box =
+                surrounding_box(output_box, triangle_box(&self.vertices_of(sample, index)));
+        }
+        output_box
+    }
+
+    fn vertex_normals(&self, index: usize) -> Option<[Vec3; 3]> {
+        if self.normals.is_empty() {
+            return None;
+        }
+        let [i0, i1, i2] = self.indices[index];
+        Some([self.normals[i0], self.normals[i1], self.normals[i2]])
+    }
+
+    fn vertex_uvs(&self, index: usize) -> Option<[(f32, f32); 3]> {
+        if self.uvs.is_empty() {
+            return None;
+        }
+        let [i0, i1, i2] = self.indices[index];
+        Some([self.uvs[i0], self.uvs[i1], self.uvs[i2]])
+    }
+
+    fn vertex_colors(&self, index: usize) -> Option<[Color; 3]> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let [i0, i1, i2] = self.indices[index];
+        Some([self.colors[i0], self.colors[i1], self.colors[i2]])
+    }
+}
+
+pub struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    pub fn new(mesh: Arc<MeshData>, index: usize) -> MeshTriangle {
+        MeshTriangle { mesh, index }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        let vertices = self.mesh.vertices(self.index, r.time());
+        let (t, b1, b2) = match intersect_triangle(r, &vertices, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        set_triangle_record(
+            r,
+            t,
+            (b1, b2),
+            &vertices,
+            self.mesh.vertex_normals(self.index).as_ref(),
+            self.mesh.vertex_uvs(self.index).as_ref(),
+            self.mesh.vertex_colors(self.index).as_ref(),
+            &self.mesh.mat,
+            rec,
+        );
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32, output_box: &mut AABB) -> bool {
+        *output_box = self.mesh.triangle_box(self.index);
+        true
+    }
+}
+
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: Option<BvhNode>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+        builder: &BvhBuilder,
+    ) -> TriangleMesh {
+        TriangleMesh::with_mesh_data(
+            Arc::new(MeshData {
+                positions,
+                position_samples: vec![],
+                time0: 0.0,
+                time1: 1.0,
+                normals,
+                uvs,
+                colors: vec![],
+                indices,
+                mat,
+            }),
+            builder,
+        )
+    }
+
+    // `normals`, `uvs` and `colors` are each empty or hold one entry per
+    // vertex; the mesh panics otherwise.
+    pub fn with_colors(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        colors: Vec<Color>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+        builder: &BvhBuilder,
+    ) -> TriangleMesh {
+        TriangleMesh::with_mesh_data(
+            Arc::new(MeshData {
+                positions,
+                position_samples: vec![],
+                time0: 0.0,
+                time1: 1.0,
+                normals,
+                uvs,
+                colors,
+                indices,
+                mat,
+            }),
+            builder,
+        )
+    }
+
+    // `position_samples` needs at least two entries, each with a position for
+    // every vertex.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_position_samples(
+        mut position_samples: Vec<Vec<Point3>>,
+        time0: f32,
+        time1: f32,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+        builder: &BvhBuilder,
+    ) -> TriangleMesh {
+        assert!(
+            position_samples.len() >= 2,
+            "a deforming mesh needs at least two position samples"
+        );
+        let positions = position_samples.remove(0);
+
+        TriangleMesh::with_mesh_data(
+            Arc::new(MeshData {
+                positions,
+                position_samples,
+                time0,
+                time1,
+                normals,
+                uvs,
+                colors: vec![],
+                indices,
+                mat,
+            }),
+            builder,
+        )
+    }
+
+    pub fn with_mesh_data(data: Arc<MeshData>, builder: &BvhBuilder) -> TriangleMesh {
+        data.check();
+        let triangles = TriangleMesh::triangles_of(&data);
+        let bvh = if triangles.objects.is_empty() {
+            None
+        } else {
+            Some(builder.build(&triangles.objects, 0.0, 1.0))
+        };
+
+        TriangleMesh { data, bvh }
+    }
+
+    pub fn data(&self) -> Arc<MeshData> {
+        Arc::clone(&self.data)
+    }
+
+    pub fn triangles(&self) -> HittableList {
+        TriangleMesh::triangles_of(&self.data)
+    }
+
+    fn triangles_of(data: &Arc<MeshData>) -> HittableList {
+        let mut triangles = HittableList::new();
+        for index in 0..data.triangle_count() {
+            triangles.add(Arc::new(MeshTriangle::new(Arc::clone(data), index)));
+        }
+        triangles
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, rec: &mut HitRecord) -> bool {
+        match &self.bvh {
+            Some(bvh) => bvh.hit(r, t_min, t_max, rec),
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32, output_box: &mut AABB) -> bool {
+        match &self.bvh {
+            Some(bvh) => bvh.bounding_box(time0, time1, output_box),
+            None => false,
+        }
+    }
+}
+
+// Moller-Trumbore intersection, returning t and the barycentric coordinates
+// of the second and third vertex. The determinant scales with the edge and
+// direction lengths, so rays are rejected as parallel relative to those.
+pub(crate) fn intersect_triangle(
+    r: &Ray,
+    vertices: &[Point3; 3],
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+
+    let pvec = Vec3::cross(&r.direction(), &edge2);
+    let det = Vec3::dot(&edge1, &pvec);
+    if det.abs() < 1e-7 * edge1.length() * edge2.length() * r.direction().length() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - vertices[0];
+    let b1 = Vec3::dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = Vec3::cross(&tvec, &edge1);
+    let b2 = Vec3::dot(&r.direction(), &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(&edge2, &qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn set_triangle_record(
+    r: &Ray,
+    t: f32,
+    barycentric: (f32, f32),
+    vertices: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: Option<&[(f32, f32); 3]>,
+    colors: Option<&[Color; 3]>,
+    mat: &Arc<dyn Material>,
+    rec: &mut HitRecord,
+) {
+    let (b1, b2) = barycentric;
+    let b0 = 1.0 - b1 - b2;
+
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let mut outward_normal = Vec3::unit_vector(&Vec3::cross(&edge1, &edge2));
+    // Vertex normals are interpolated for shading and also decide which
+    // side of the triangle is the outside.
+    let mut shading_normal = outward_normal;
+    if let Some(n) = normals {
+        let interpolated = b0 * n[0] + b1 * n[1] + b2 * n[2];
+        if Vec3::dot(&interpolated, &outward_normal) < 0.0 {
+            outward_normal = -outward_normal;
+        }
+        if !interpolated.near_zero() {
+            shading_normal = Vec3::unit_vector(&interpolated);
+        }
+    }
+
+    // Without texture coordinates u and v are the barycentric coordinates,
+    // so the edges are the derivatives.
+    let (u, v, tangent, bitangent) = match uvs {
+        Some(uv) => {
+            let (du1, dv1) = (uv[1].0 - uv[0].0, uv[1].1 - uv[0].1);
+            let (du2, dv2) = (uv[2].0 - uv[0].0, uv[2].1 - uv[0].1);
+            let det = du1 * dv2 - du2 * dv1;
+            let (tangent, bitangent) = if det.abs() > 1e-12 {
+                (
+                    (dv2 * edge1 - dv1 * edge2) / det,
+                    (du1 * edge2 - du2 * edge1) / det,
+                )
+            } else {
+                (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0))
+            };
+            (
+                b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+                b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+                tangent,
+                bitangent,
+            )
+        }
+        None => (b1, b2, edge1, edge2),
+    };
+
+    rec.t = t;
+    rec.p = r.at(t);
+    rec.set_face_normals(r, &outward_normal, &shading_normal);
+    rec.tangent = tangent;
+    rec.bitangent = bitangent;
+    rec.u = u;
+    rec.v = v;
+    rec.barycentric = barycentric;
+    rec.vertex_color = colors.map(|c| b0 * c[0] + b1 * c[1] + b2 * c[2]);
+    rec.mat = Arc::clone(mat);
+}
+
+fn triangle_box(vertices: &[Point3; 3]) -> AABB {
+    let mut min_point = vertices[0];
+    let mut max_point = vertices[0];
+    for vertex in &vertices[1..] {
+        for c in 0..3 {
+            min_point[c] = f32::min(min_point[c], vertex[c]);
+            max_point[c] = f32::max(max_point[c], vertex[c]);
+        }
+    }
+
+    for c in 0..3 {
+        if max_point[c] - min_point[c] < 0.0001 {
+            min_point[c] -= 0.0001;
+            max_point[c] += 0.0001;
+        }
+    }
+
+    AABB::new(&min_point, &max_point)
+}
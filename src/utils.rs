@@ -1,15 +1,35 @@
+use crate::aabb::AABB;
 use crate::aarec::{XYRect, XZRect, YZRect};
-use crate::bvh::BvhNode;
+use crate::bvh::BvhBuilder;
 use crate::constant_medium::ConstantMedium;
+use crate::csg::Csg;
 use crate::cube::Cube;
-use crate::hittable::{HitRecord, Hittable, RotateY, Translate};
-use crate::material::DiffuseLight;
+use crate::framebuffer::PixelAov;
+use crate::heightfield::Heightfield;
+use crate::hittable::{HitRecord, Hittable, RotateY, Transformed, Translate};
+use crate::instance::Instance;
+use crate::mat4::{Mat4, Transform};
+use crate::material::{DiffuseLight, Material, NormalMapped};
 use crate::moving_sphere::MovingSphere;
+use crate::obj_loader::{load_obj, ObjError};
+use crate::perlin::Perlin;
+use crate::planar::{Disk, Plane, Quad};
+use crate::ply_loader::load_ply;
+use crate::quadric::{Cone, Cylinder, Hyperboloid, Paraboloid};
 use crate::ray::Ray;
-use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor};
+use crate::scene::SceneError;
+use crate::sdf::{
+    Repeat, SdfBox, SdfCapsule, SdfObject, SdfSphere, SdfTorus, SmoothSubtraction, SmoothUnion,
+    Twist,
+};
+use crate::stl_loader::load_stl;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, VertexColorTexture};
+use crate::torus::Torus;
+use crate::triangle::{Triangle, TriangleMesh};
 use crate::vec3::Vec3;
-use rand::{self, Rng};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use std::{
+    cell::RefCell,
     f32::consts::PI,
     f32::EPSILON,
     io::{self, Write},
@@ -28,9 +48,29 @@ pub fn degrees_to_radians(degrees: f32) -> f32 {
     degrees * PI / 180.0
 }
 
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Every (pixel, sample) pair gets its own stream, so the image does not
+// depend on which thread renders which row.
+pub fn seed_sample(seed: u64, pixel: u64, sample: u64) {
+    seed_random(splitmix64(splitmix64(splitmix64(seed) ^ pixel) ^ sample));
+}
+
 pub fn random_f32() -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
 }
 
 pub fn random_f32_with_range(min: f32, max: f32) -> f32 {
@@ -45,6 +85,7 @@ pub struct PixelInfo {
     pub u: usize,
     pub v: usize,
     pub color: Color,
+    pub aov: Option<PixelAov>,
 }
 
 pub fn format_duration_hhmmss(duration: Duration) -> String {
@@ -84,6 +125,25 @@ pub fn ray_color(r: &Ray, background: &Color, world: Arc<dyn Hittable>, depth: i
     emitted + attenuation * ray_color(&scattered, background, world, depth - 1)
 }
 
+pub fn first_hit_aov(r: &Ray, world: &Arc<dyn Hittable>) -> PixelAov {
+    let mut rec = HitRecord::new();
+    if !world.hit(r, f32::EPSILON * 9e4, f32::MAX, &mut rec) {
+        return PixelAov::default();
+    }
+
+    let mut scattered = Ray::default();
+    let mut albedo = Color::default();
+    if !rec.mat.scatter(r, &rec, &mut albedo, &mut scattered) {
+        albedo = rec.mat.emitted(rec.u, rec.v, &rec.p);
+    }
+
+    PixelAov {
+        albedo,
+        normal: rec.shading_normal,
+        depth: rec.t * r.direction().length(),
+    }
+}
+
 pub fn random_scene() -> HittableList {
     let mut world = HittableList::new();
 
@@ -220,7 +280,7 @@ pub fn two_perlin_shpheres() -> HittableList {
 pub fn hittable_list_earth() -> HittableList {
     let mut objects = HittableList::new();
 
-    let image_texture = Arc::new(ImageTexture::new("assets/earthmap.jpg".to_string()));
+    let image_texture = Arc::new(ImageTexture::new("assets/earthmap.jpg".to_string(), true));
     let earth_surface = Arc::new(Lambertian::new(image_texture));
 
     objects.add(Arc::new(Sphere::with_center_and_radius(
@@ -429,7 +489,7 @@ pub fn cornell_box_smoke() -> HittableList {
     objects
 }
 
-pub fn hittalbe_list_final_scene() -> HittableList {
+pub fn hittalbe_list_final_scene(builder: &BvhBuilder) -> HittableList {
     let mut boxes1 = HittableList::new();
     let ground = Arc::new(Lambertian::with_color(&Color::new(0.48, 0.83, 0.53)));
 
@@ -454,7 +514,7 @@ pub fn hittalbe_list_final_scene() -> HittableList {
 
     let mut objects = HittableList::new();
 
-    objects.add(Arc::new(BvhNode::with_hittable_list(&boxes1, 0.0, 1.0)));
+    objects.add(Arc::new(builder.build(&boxes1.objects, 0.0, 1.0)));
 
     let light = Arc::new(DiffuseLight::with_color(Color::new(7.0, 7.0, 7.0)));
     objects.add(Arc::new(XZRect::new(
@@ -508,6 +568,7 @@ pub fn hittalbe_list_final_scene() -> HittableList {
 
     let earth_material = Arc::new(Lambertian::new(Arc::new(ImageTexture::new(
         "assets/earthmap.jpg".to_string(),
+        true,
     ))));
     objects.add(Arc::new(Sphere::with_center_and_radius(
         Point3::new(400.0, 200.0, 400.0),
@@ -535,7 +596,7 @@ pub fn hittalbe_list_final_scene() -> HittableList {
 
     objects.add(Arc::new(Translate::new(
         Arc::new(RotateY::new(
-            Arc::new(BvhNode::with_hittable_list(&boxes2, 0.0, 1.0)),
+            Arc::new(builder.build(&boxes2.objects, 0.0, 1.0)),
             15.0,
         )),
         &Vec3::new(-100.0, 270.0, 395.0),
@@ -544,6 +605,534 @@ pub fn hittalbe_list_final_scene() -> HittableList {
     objects
 }
 
+pub fn hittable_list_triangles(builder: &BvhBuilder) -> HittableList {
+    let mut objects = HittableList::new();
+
+    let checker = Arc::new(CheckerTexture::with_color(
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(checker)),
+    )));
+
+    let positions = vec![
+        Point3::new(-1.5, 0.0, -1.5),
+        Point3::new(1.5, 0.0, -1.5),
+        Point3::new(1.5, 0.0, 1.5),
+        Point3::new(-1.5, 0.0, 1.5),
+        Point3::new(0.0, 3.0, 0.0),
+    ];
+    let indices = vec![
+        [0, 1, 4],
+        [1, 2, 4],
+        [2, 3, 4],
+        [3, 0, 4],
+        [0, 2, 1],
+        [0, 3, 2],
+    ];
+    let pyramid = TriangleMesh::new(
+        positions,
+        vec![],
+        vec![],
+        indices,
+        Arc::new(Lambertian::with_color(&Color::new(0.8, 0.3, 0.1))),
+        builder,
+    );
+    objects.add(Arc::new(pyramid));
+
+    objects.add(Arc::new(Triangle::new(
+        Point3::new(-1.0, 0.0, -4.0),
+        Point3::new(3.0, 0.0, -4.0),
+        Point3::new(1.0, 3.5, -4.0),
+        Arc::new(Metal::new(&Color::new(0.7, 0.6, 0.5), 0.05)),
+    )));
+
+    objects
+}
+
+pub fn hittable_list_obj_model(builder: &BvhBuilder) -> Result<HittableList, ObjError> {
+    let mut objects = load_obj("assets/models/obj_demo.obj", builder)?;
+
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::with_color(&Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    Ok(objects)
+}
+
+pub fn hittable_list_scans(builder: &BvhBuilder) -> Result<HittableList, SceneError> {
+    let mut objects = HittableList::new();
+
+    let vertex_colors = Arc::new(Lambertian::new(Arc::new(VertexColorTexture::with_color(
+        Color::new(0.73, 0.73, 0.73),
+    ))));
+    objects.add(Arc::new(load_ply(
+        "assets/models/ply_demo.ply",
+        vertex_colors,
+        builder,
+    )?));
+    objects.add(Arc::new(load_stl(
+        "assets/models/stl_demo.stl",
+        Arc::new(Metal::new(&Color::new(0.8, 0.6, 0.3), 0.2)),
+        builder,
+    )?));
+
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::with_color(&Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    Ok(objects)
+}
+
+pub fn hittable_list_shading_normals(builder: &BvhBuilder) -> Result<HittableList, SceneError> {
+    let mut objects = HittableList::new();
+    let tiles = Arc::new(ImageTexture::new(
+        "assets/normal_map_tiles.png".to_string(),
+        false,
+    ));
+
+    objects.add(Arc::new(Plane::with_tile_size(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        2.0,
+        Arc::new(NormalMapped::new(
+            Arc::new(Lambertian::with_color(&Color::new(0.6, 0.6, 0.55))),
+            tiles.clone(),
+        )),
+    )));
+
+    // The same coarse sphere, faceted without normals and smooth with them.
+    let chrome = Arc::new(Metal::new(&Color::new(0.8, 0.8, 0.85), 0.0));
+    objects.add(Arc::new(load_stl(
+        "assets/models/lowpoly_sphere.stl",
+        chrome.clone(),
+        builder,
+    )?));
+    objects.add(Arc::new(load_ply(
+        "assets/models/lowpoly_sphere.ply",
+        chrome,
+        builder,
+    )?));
+
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(2.0, 0.8, 0.0),
+        0.8,
+        Arc::new(NormalMapped::new(
+            Arc::new(Lambertian::with_color(&Color::new(0.7, 0.25, 0.15))),
+            tiles,
+        )),
+    )));
+
+    Ok(objects)
+}
+
+pub fn hittable_list_quads() -> HittableList {
+    let mut objects = HittableList::new();
+
+    objects.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.6, 0.6, 0.55))),
+    )));
+
+    objects.add(Arc::new(Quad::new(
+        Point3::new(-3.5, 0.0, 0.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.5, -1.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.8, 0.2, 0.2))),
+    )));
+    objects.add(Arc::new(Quad::new(
+        Point3::new(1.5, 0.0, -0.5),
+        Vec3::new(1.5, 0.0, -1.2),
+        Vec3::new(-0.5, 2.5, 0.0),
+        Arc::new(Metal::new(&Color::new(0.8, 0.85, 0.9), 0.02)),
+    )));
+    objects.add(Arc::new(Disk::new(
+        Point3::new(0.0, 1.2, 1.0),
+        Vec3::new(0.3, 0.2, 1.0),
+        1.0,
+        Arc::new(Lambertian::with_color(&Color::new(0.2, 0.4, 0.8))),
+    )));
+
+    objects.add(Arc::new(Quad::new(
+        Point3::new(-1.5, 4.0, -1.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(0.0, -0.8, 2.0),
+        Arc::new(DiffuseLight::with_color(Color::new(6.0, 6.0, 6.0))),
+    )));
+
+    objects
+}
+
+pub fn hittable_list_shapes() -> HittableList {
+    let mut objects = HittableList::new();
+
+    objects.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.6, 0.6, 0.55))),
+    )));
+
+    objects.add(Arc::new(Cylinder::with_options(
+        Point3::new(-3.0, 0.0, 0.0),
+        0.6,
+        2.0,
+        360.0,
+        true,
+        Arc::new(Metal::new(&Color::new(0.8, 0.85, 0.9), 0.1)),
+    )));
+    objects.add(Arc::new(Cone::with_options(
+        Point3::new(-1.2, 0.0, 0.5),
+        0.7,
+        1.6,
+        360.0,
+        true,
+        Arc::new(Lambertian::with_color(&Color::new(0.8, 0.2, 0.2))),
+    )));
+    objects.add(Arc::new(Paraboloid::new(
+        Point3::new(0.6, 0.0, 0.0),
+        0.8,
+        1.2,
+        Arc::new(Metal::new(&Color::new(0.9, 0.7, 0.3), 0.05)),
+    )));
+    objects.add(Arc::new(Hyperboloid::with_options(
+        Point3::new(2.6, 0.0, 0.0),
+        0.7,
+        0.35,
+        2.0,
+        360.0,
+        true,
+        Arc::new(Lambertian::with_color(&Color::new(0.2, 0.4, 0.8))),
+    )));
+
+    // A cut-away pipe and an elbow show partial sweeps.
+    objects.add(Arc::new(Cylinder::with_options(
+        Point3::new(2.2, 0.0, 2.2),
+        0.5,
+        0.8,
+        270.0,
+        false,
+        Arc::new(Lambertian::with_color(&Color::new(0.8, 0.8, 0.8))),
+    )));
+    objects.add(Arc::new(Torus::with_sweep(
+        Point3::new(-2.4, 0.25, 2.2),
+        0.6,
+        0.2,
+        270.0,
+        Arc::new(Metal::new(&Color::new(0.7, 0.5, 0.4), 0.2)),
+    )));
+    objects.add(Arc::new(Torus::new(
+        Point3::new(0.0, 0.3, 2.2),
+        0.8,
+        0.3,
+        Arc::new(Lambertian::with_color(&Color::new(0.2, 0.7, 0.3))),
+    )));
+
+    objects.add(Arc::new(Quad::new(
+        Point3::new(-1.5, 5.0, -1.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Arc::new(DiffuseLight::with_color(Color::new(6.0, 6.0, 6.0))),
+    )));
+
+    objects
+}
+
+pub fn hittable_list_csg() -> HittableList {
+    let mut objects = HittableList::new();
+
+    objects.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.6, 0.6, 0.55))),
+    )));
+
+    // A sphere with a hole drilled through it along z.
+    let drill = Cylinder::with_options(
+        Point3::new(0.0, -1.5, 0.0),
+        0.45,
+        3.0,
+        360.0,
+        true,
+        Arc::new(Lambertian::with_color(&Color::new(0.9, 0.9, 0.9))),
+    );
+    let drill = Transformed::new(
+        Arc::new(drill),
+        Transform::new(Mat4::translation(&Vec3::new(-2.4, 1.0, 0.0)) * Mat4::rotation_x(90.0))
+            .unwrap(),
+    );
+    objects.add(Arc::new(Csg::difference(
+        Arc::new(Sphere::with_center_and_radius(
+            Point3::new(-2.4, 1.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::with_color(&Color::new(0.8, 0.2, 0.2))),
+        )),
+        Arc::new(drill),
+    )));
+
+    // A biconvex lens from two overlapping spheres.
+    let glass = Arc::new(Dielectric::new(1.5));
+    objects.add(Arc::new(Csg::intersection(
+        Arc::new(Sphere::with_center_and_radius(
+            Point3::new(-1.2, 1.0, 0.0),
+            1.5,
+            glass.clone(),
+        )),
+        Arc::new(Sphere::with_center_and_radius(
+            Point3::new(1.2, 1.0, 0.0),
+            1.5,
+            glass,
+        )),
+    )));
+
+    // A die: a cube rounded off by a sphere, with a hole through the top.
+    let die = Csg::intersection(
+        Arc::new(Cube::new(
+            &Point3::new(1.7, 0.0, -0.7),
+            &Point3::new(3.1, 1.4, 0.7),
+            Arc::new(Metal::new(&Color::new(0.8, 0.85, 0.9), 0.05)),
+        )),
+        Arc::new(Sphere::with_center_and_radius(
+            Point3::new(2.4, 0.7, 0.0),
+            0.95,
+            Arc::new(Lambertian::with_color(&Color::new(0.2, 0.4, 0.8))),
+        )),
+    );
+    objects.add(Arc::new(Csg::difference(
+        Arc::new(die),
+        Arc::new(Cylinder::with_options(
+            Point3::new(2.4, -0.1, 0.0),
+            0.3,
+            1.6,
+            360.0,
+            true,
+            Arc::new(Lambertian::with_color(&Color::new(0.9, 0.7, 0.3))),
+        )),
+    )));
+
+    objects.add(Arc::new(Quad::new(
+        Point3::new(-1.5, 5.0, -1.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Arc::new(DiffuseLight::with_color(Color::new(6.0, 6.0, 6.0))),
+    )));
+
+    objects
+}
+
+pub fn hittable_list_sdf() -> HittableList {
+    let mut objects = HittableList::new();
+
+    objects.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.6, 0.6, 0.55))),
+    )));
+
+    // A rounded box with a smooth bowl carved into its top.
+    let bowl = SmoothSubtraction::new(
+        Arc::new(SdfBox::rounded(
+            Point3::new(-2.6, 0.7, 0.0),
+            Vec3::new(0.7, 0.7, 0.7),
+            0.15,
+        )),
+        Arc::new(SdfSphere::new(Point3::new(-2.6, 1.6, 0.0), 0.8)),
+        0.2,
+    );
+    objects.add(Arc::new(SdfObject::new(
+        Arc::new(bowl),
+        AABB::new(
+            &Point3::new(-3.31, -0.01, -0.71),
+            &Point3::new(-1.89, 1.41, 0.71),
+        ),
+        Arc::new(Lambertian::with_color(&Color::new(0.8, 0.2, 0.2))),
+    )));
+
+    // A square column twisted about the y axis.
+    let column = Twist::new(
+        Arc::new(SdfBox::new(
+            Point3::new(0.0, 1.2, 0.0),
+            Vec3::new(0.35, 1.2, 0.35),
+        )),
+        60.0,
+    );
+    objects.add(Arc::new(SdfObject::new(
+        Arc::new(column),
+        AABB::new(
+            &Point3::new(-0.51, -0.01, -0.51),
+            &Point3::new(0.51, 2.41, 0.51),
+        ),
+        Arc::new(Metal::new(&Color::new(0.8, 0.85, 0.9), 0.05)),
+    )));
+
+    // A torus and a capsule blended into one organic shape.
+    let blob = SmoothUnion::new(
+        Arc::new(SdfTorus::new(Point3::new(2.6, 0.35, 0.0), 0.7, 0.25)),
+        Arc::new(SdfCapsule::new(
+            Point3::new(2.6, 0.35, 0.0),
+            Point3::new(2.6, 1.6, 0.0),
+            0.25,
+        )),
+        0.3,
+    );
+    objects.add(Arc::new(SdfObject::new(
+        Arc::new(blob),
+        AABB::new(&Point3::new(1.6, -0.01, -1.0), &Point3::new(3.6, 1.9, 1.0)),
+        Arc::new(Lambertian::with_color(&Color::new(0.2, 0.4, 0.8))),
+    )));
+
+    // One sphere repeated along x, clipped to a row by the bounds.
+    let row = Repeat::new(
+        Arc::new(SdfSphere::new(Point3::new(0.0, 0.2, 2.2), 0.2)),
+        Vec3::new(0.6, 0.0, 0.0),
+    );
+    objects.add(Arc::new(SdfObject::new(
+        Arc::new(row),
+        AABB::new(
+            &Point3::new(-3.21, -0.01, 1.99),
+            &Point3::new(3.21, 0.41, 2.41),
+        ),
+        Arc::new(Dielectric::new(1.5)),
+    )));
+
+    objects.add(Arc::new(Quad::new(
+        Point3::new(-1.5, 5.0, -1.0),
+        Vec3::new(3.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Arc::new(DiffuseLight::with_color(Color::new(6.0, 6.0, 6.0))),
+    )));
+
+    objects
+}
+
+pub fn hittable_list_terrain() -> HittableList {
+    let mut objects = HittableList::new();
+
+    // 513 x 513 height samples: half a million triangles that are never
+    // stored as meshes.
+    objects.add(Arc::new(Heightfield::from_noise(
+        &Perlin::new(),
+        0.04,
+        5,
+        513,
+        513,
+        Point3::new(-50.0, 0.0, -50.0),
+        Vec3::new(100.0, 12.0, 100.0),
+        Arc::new(Lambertian::with_color(&Color::new(0.48, 0.83, 0.53))),
+    )));
+
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(-8.0, 18.0, 0.0),
+        5.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(8.0, 18.0, -5.0),
+        5.0,
+        Arc::new(Metal::new(&Color::new(0.8, 0.85, 0.9), 0.0)),
+    )));
+
+    objects
+}
+
+fn tree_foliage(builder: &BvhBuilder) -> TriangleMesh {
+    const SEGMENTS: usize = 10;
+    let tiers = [(0.6, 1.4, 0.9), (1.3, 1.2, 0.7), (1.9, 1.0, 0.5)];
+
+    let mut positions = vec![];
+    let mut indices = vec![];
+    for (base, height, radius) in tiers {
+        let first = positions.len();
+        for i in 0..SEGMENTS {
+            let phi = 2.0 * PI * i as f32 / SEGMENTS as f32;
+            positions.push(Point3::new(radius * phi.cos(), base, radius * phi.sin()));
+        }
+        let apex = positions.len();
+        positions.push(Point3::new(0.0, base + height, 0.0));
+        let center = positions.len();
+        positions.push(Point3::new(0.0, base, 0.0));
+
+        for i in 0..SEGMENTS {
+            let a = first + i;
+            let b = first + (i + 1) % SEGMENTS;
+            indices.push([a, apex, b]);
+            indices.push([a, b, center]);
+        }
+    }
+
+    TriangleMesh::new(
+        positions,
+        vec![],
+        vec![],
+        indices,
+        Arc::new(Lambertian::with_color(&Color::new(0.13, 0.35, 0.12))),
+        builder,
+    )
+}
+
+pub fn hittable_list_forest(builder: &BvhBuilder) -> HittableList {
+    let mut objects = HittableList::new();
+
+    objects.add(Arc::new(Sphere::with_center_and_radius(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::with_color(&Color::new(0.35, 0.45, 0.2))),
+    )));
+
+    // One copy of each mesh, shared by every tree.
+    let trunk: Arc<dyn Hittable> = Arc::new(Cube::new(
+        &Point3::new(-0.12, 0.0, -0.12),
+        &Point3::new(0.12, 0.8, 0.12),
+        Arc::new(Lambertian::with_color(&Color::new(0.3, 0.18, 0.08))),
+    ));
+    let foliage: Arc<dyn Hittable> = Arc::new(tree_foliage(builder));
+
+    let autumn: Vec<Arc<dyn Material>> = vec![
+        Arc::new(Lambertian::with_color(&Color::new(0.75, 0.35, 0.05))),
+        Arc::new(Lambertian::with_color(&Color::new(0.7, 0.55, 0.1))),
+        Arc::new(Lambertian::with_color(&Color::new(0.55, 0.12, 0.05))),
+    ];
+
+    for a in -16..16 {
+        for b in -16..16 {
+            let position = Point3::new(
+                2.0 * a as f32 + 1.6 * random_f32(),
+                0.0,
+                2.0 * b as f32 + 1.6 * random_f32(),
+            );
+            let width = random_f32_with_range(0.7, 1.2);
+            let transform = Transform::new(
+                Mat4::translation(&position)
+                    * Mat4::rotation_y(random_f32_with_range(0.0, 360.0))
+                    * Mat4::scaling(&Vec3::new(
+                        width,
+                        width * random_f32_with_range(0.8, 1.5),
+                        width,
+                    )),
+            )
+            .unwrap();
+
+            objects.add(Arc::new(Instance::new(Arc::clone(&trunk), transform)));
+            let foliage = if random_f32() < 0.3 {
+                let material = &autumn[random_i32_with_range(0, autumn.len() as i32 - 1) as usize];
+                Instance::with_material(Arc::clone(&foliage), transform, Arc::clone(material))
+            } else {
+                Instance::new(Arc::clone(&foliage), transform)
+            };
+            objects.add(Arc::new(foliage));
+        }
+    }
+
+    objects
+}
+
 pub fn print_progress(
     current_pixel: usize,
     total_pixels: usize,
@@ -0,0 +1,30 @@
+use basic_ray_tracing::{framebuffer::Framebuffer, scene::builtin_scene, Renderer};
+
+fn render(threads: usize) -> Framebuffer {
+    let mut scene = builtin_scene("cornell_box", None).unwrap();
+    scene.render.image_width = 24;
+    scene.render.aspect_ratio = 1.0;
+    scene.render.samples_per_pixel = 4;
+    scene.render.max_depth = 8;
+    scene.render.seed = 7;
+    let camera = scene.camera.build(scene.render.aspect_ratio);
+    Renderer::new(scene.world, camera, scene.render, threads)
+        .render()
+        .unwrap()
+}
+
+// Every pixel draws its samples from a stream seeded by the pixel and the
+// sample index, so the thread count must not change the image.
+#[test]
+fn same_seed_renders_same_pixels_on_any_thread_count() {
+    let single = render(1);
+    let parallel = render(4);
+
+    assert_eq!(single.color.len(), 24 * 24);
+    for (a, b) in single.color.iter().zip(&parallel.color) {
+        assert_eq!(
+            [a.x(), a.y(), a.z()].map(f32::to_bits),
+            [b.x(), b.y(), b.z()].map(f32::to_bits)
+        );
+    }
+}